@@ -1,8 +1,6 @@
-#![feature(concat_idents)]
-
 use std::time::Duration;
 
-use brie::{simple_hash, sorted, vanilla, Oneshot};
+use brie::{leaf::Set, prehash, prehash::BuildPreHasher, simple_hash, sorted, vanilla, Oneshot};
 use bumpalo::Bump;
 use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, SamplingMode};
 use rand::prelude::*;
@@ -70,6 +68,30 @@ macro_rules! build_nested {
     };
 }
 
+macro_rules! build_nested_par {
+    ($g:expr, $name:expr, $workers:expr, $sz:expr, $build:expr) => {
+        let name = format!("{} x{}", $name, $workers);
+        $g.bench_with_input(BenchmarkId::new(name, $sz), &$sz, |b, sz| {
+            let mut iter = iproduct!(0..*sz, 0..*sz, 0..*sz, 0..*sz, 0..*sz)
+                .map(|(x, y, z, a, b)| [x, y, z, a, b])
+                .collect::<Vec<_>>();
+            let mut rng = thread_rng();
+            iter.shuffle(&mut rng);
+
+            b.iter_batched(
+                || {
+                    (
+                        iter.clone(),
+                        (0..$workers).map(|_| Bump::new()).collect::<Vec<_>>(),
+                    )
+                },
+                |(vs, mut arenas)| $build(vs, &mut arenas),
+                BatchSize::SmallInput,
+            );
+        });
+    };
+}
+
 macro_rules! intersect_flat {
     ($g:expr, $ty:ty, $sz:expr) => {
         $g.bench_with_input(BenchmarkId::new(stringify!($ty), $sz), &$sz, |b, sz| {
@@ -101,6 +123,7 @@ macro_rules! intersect_flat {
     };
 }
 
+#[allow(unused_macros)]
 macro_rules! intersect_nested {
     ($g:expr, $ty:ty, $sz:expr) => {
         $g.bench_with_input(BenchmarkId::new(stringify!($ty), $sz), &$sz, |b, sz| {
@@ -131,6 +154,29 @@ macro_rules! intersect_nested {
     };
 }
 
+macro_rules! map_lookup {
+    ($g:expr, $ty:ty, $sz:expr) => {
+        $g.bench_with_input(BenchmarkId::new(stringify!($ty), $sz), &$sz, |b, sz| {
+            let a = Bump::new();
+            let mut keys = (0..*sz).collect::<Vec<_>>();
+            let mut rng = thread_rng();
+            keys.shuffle(&mut rng);
+
+            // Chunky values, so that AoS drags them through cache while searching
+            let mut m = <$ty>::with_capacity(*sz, &a);
+            for k in keys.iter() {
+                m.insert(*k, [0u64; 4], &a);
+            }
+
+            b.iter(|| {
+                for k in keys.iter() {
+                    let _v = m.get(k);
+                }
+            });
+        });
+    };
+}
+
 fn bench_build_flat(c: &mut Criterion) {
     let mut group = c.benchmark_group("trie, build flat (1 layer)");
 
@@ -139,8 +185,9 @@ fn bench_build_flat(c: &mut Criterion) {
         build_flat!(group, vanilla::BumpTrie<_>, upper);
         build_flat!(group, vanilla::FancyTrie<_>, upper);
         build_flat!(group, sorted::Trie<_>, upper);
+        build_flat!(group, sorted::soa::Trie<_>, upper);
         // build_flat!(group, simple_hash::Trie<_, 1>, upper);
-        // build_flat!(group, hash::ManagedTrie<_, 1>, upper);
+        // build_flat!(group, hash::Trie<_, 1>, upper);
     }
 }
 
@@ -152,8 +199,9 @@ fn bench_build_mid(c: &mut Criterion) {
         build_mid!(group, vanilla::BumpTrie<_>, upper);
         build_mid!(group, vanilla::FancyTrie<_>, upper);
         build_mid!(group, sorted::Trie<_>, upper);
+        build_mid!(group, sorted::soa::Trie<_>, upper);
         // build_mid!(group, simple_hash::Trie<_, 3>, upper);
-        // build_mid!(group, hash::ManagedTrie<_, 3>, upper);
+        // build_mid!(group, hash::Trie<_, 3>, upper);
     }
 }
 
@@ -165,8 +213,27 @@ fn bench_build_nested(c: &mut Criterion) {
         build_nested!(group, vanilla::BumpTrie<_>, upper);
         build_nested!(group, vanilla::FancyTrie<_>, upper);
         build_nested!(group, sorted::Trie<_>, upper);
-        // build_nested!(group, simple_hash::Trie<_, 5>, upper);
-        // build_nested!(group, hash::ManagedTrie<_, 5>, upper);
+        build_nested!(group, sorted::soa::Trie<_>, upper);
+        build_nested!(group, simple_hash::Trie<_, 5>, upper);
+        // build_nested!(group, hash::Trie<_, 5>, upper);
+        build_nested_par!(
+            group,
+            "sorted::Trie<_>",
+            4,
+            upper,
+            |vs, arenas: &mut [Bump]| {
+                sorted::Trie::par_from_iter(vs, arenas);
+            }
+        );
+        build_nested_par!(
+            group,
+            "simple_hash::Trie<_, 5>",
+            4,
+            upper,
+            |vs, arenas: &mut [Bump]| {
+                simple_hash::Trie::<_, 5>::par_from_iter(vs, Default::default(), arenas);
+            }
+        );
     }
 }
 
@@ -179,15 +246,31 @@ fn bench_intersect_flat(c: &mut Criterion) {
         intersect_flat!(group, vanilla::BumpTrie<_>, upper);
         intersect_flat!(group, vanilla::FancyTrie<_>, upper);
         intersect_flat!(group, sorted::Trie<_>, upper);
-        // intersect_flat!(group, simple_hash::Trie<_, 1>, upper);
-        // intersect_nested!(group, hash::ManagedTrie<_, 5>, upper);
+        intersect_flat!(group, sorted::soa::Trie<_>, upper);
+        intersect_flat!(group, simple_hash::Trie<_, 1>, upper);
+        // Same tries, hashing keys with a single multiply
+        intersect_flat!(group, vanilla::Trie<_, BuildPreHasher>, upper);
+        intersect_flat!(group, vanilla::FancyTrie<_, Set, BuildPreHasher>, upper);
+        intersect_flat!(group, simple_hash::Trie<_, 1, Set, BuildPreHasher>, upper);
+        // Storing the keys themselves instead of their hashes
+        intersect_flat!(group, prehash::Trie<_, 1>, upper);
+        // intersect_nested!(group, hash::Trie<_, 5>, upper);
+    }
+}
+
+fn bench_map_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sorted map, lookup (AoS vs SoA)");
+
+    for upper in [100, 1_000, 10_000, 100_000] {
+        map_lookup!(group, sorted::Map<_, _>, upper);
+        map_lookup!(group, sorted::soa::Map<_, _>, upper);
     }
 }
 
 criterion_group! {
     name = benches;
     config = Criterion::default().measurement_time(Duration::from_millis(500)).warm_up_time(Duration::from_millis(100)).sample_size(10);
-    targets = bench_build_flat, bench_build_mid, bench_build_nested, bench_intersect_flat
+    targets = bench_build_flat, bench_build_mid, bench_build_nested, bench_intersect_flat, bench_map_lookup
     // targets = bench_intersect_flat
 }
 criterion_main!(benches);
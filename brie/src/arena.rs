@@ -0,0 +1,239 @@
+//! Arenas with a memory budget.
+//!
+//! Every trie allocates from a caller-supplied [`Bump`], which will grow for
+//! as long as the system allocator lets it. An [`Arena`] is a [`Bump`] with
+//! a budget: tries built with [`Arena::try_from_iter`] fail with a
+//! [`BuildError`] once they'd take the arena past it, instead of taking the
+//! whole process down with them.
+//!
+//! Only [`TryOneshot`] tries can fail cleanly. The rest can still be built in
+//! an [`Arena`], since it derefs to a [`Bump`], but they abort on running out
+//! of budget just as they would on running out of memory.
+
+use std::{error::Error, fmt, ops};
+
+use bumpalo::Bump;
+
+use crate::{sorted::vec::CollectionAllocError, TryOneshot};
+
+/// A [`Bump`] which allocates at most `budget` bytes.
+///
+/// The budget is only checked when the arena needs a new chunk, so a trie
+/// may be built which fits in the space already reserved by the arena.
+#[derive(Debug, Default)]
+pub struct Arena {
+    bump: Bump,
+}
+
+impl Arena {
+    /// An arena without a budget.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An arena which won't allocate more than `budget` bytes.
+    pub fn with_budget(budget: usize) -> Self {
+        let res = Self::new();
+        res.set_budget(Some(budget));
+        res
+    }
+
+    #[inline]
+    pub fn budget(&self) -> Option<usize> {
+        self.bump.allocation_limit()
+    }
+
+    /// Changes the budget. Shrinking it below [`Arena::allocated`] doesn't
+    /// free anything, but the arena won't grow any further.
+    #[inline]
+    pub fn set_budget(&self, budget: Option<usize>) {
+        self.bump.set_allocation_limit(budget)
+    }
+
+    /// Bytes the arena has taken from the system allocator. This is what's
+    /// counted against the budget.
+    #[inline]
+    pub fn allocated(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+
+    /// Bytes handed out to tries, which is [`Arena::allocated`] less the
+    /// room left in the current chunk.
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.bump.allocated_bytes() - self.bump.chunk_capacity()
+    }
+
+    /// Builds a trie with [`TryOneshot::try_from_iter`], recording how many
+    /// bytes it used.
+    pub fn try_from_iter<'bump, T, I, const N: usize>(
+        &'bump self,
+        iter: I,
+    ) -> Result<Built<T>, BuildError>
+    where
+        T: TryOneshot<'bump, N>,
+        I: IntoIterator<Item = [T::Value; N]>,
+    {
+        self.try_build(|bump| T::try_from_iter(iter, bump))
+    }
+
+    /// Runs `f` on this arena's [`Bump`], recording how many bytes it used
+    /// and turning allocation failures into a [`BuildError`].
+    pub fn try_build<'bump, T, F>(&'bump self, f: F) -> Result<Built<T>, BuildError>
+    where
+        F: FnOnce(&'bump Bump) -> Result<T, CollectionAllocError>,
+    {
+        let before = self.used();
+        match f(&self.bump) {
+            Ok(trie) => Ok(Built {
+                trie,
+                bytes: self.used() - before,
+            }),
+            Err(CollectionAllocError::CapacityOverflow) => Err(BuildError::CapacityOverflow),
+            Err(CollectionAllocError::AllocError) => Err(match self.budget() {
+                Some(budget) => BuildError::OverBudget {
+                    budget,
+                    allocated: self.allocated(),
+                },
+                None => BuildError::OutOfMemory,
+            }),
+        }
+    }
+}
+
+impl ops::Deref for Arena {
+    type Target = Bump;
+
+    fn deref(&self) -> &Bump {
+        &self.bump
+    }
+}
+
+/// A trie built by an [`Arena`], along with the bytes it used.
+#[derive(Debug)]
+pub struct Built<T> {
+    pub trie: T,
+    /// Bytes taken from the arena while building `trie`, including any spare
+    /// capacity its buffers were left with.
+    pub bytes: usize,
+}
+
+/// Why an [`Arena`] couldn't build a trie. Whatever was allocated before the
+/// failure stays in the arena until it's dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// The trie didn't fit in the arena's budget.
+    OverBudget { budget: usize, allocated: usize },
+    /// The arena has no budget, but the system allocator ran out.
+    OutOfMemory,
+    /// Some buffer would have been bigger than `isize::MAX` bytes.
+    CapacityOverflow,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::OverBudget { budget, allocated } => write!(
+                f,
+                "trie exceeds the arena's budget of {} bytes ({} already allocated)",
+                budget, allocated
+            ),
+            BuildError::OutOfMemory => write!(f, "out of memory building trie"),
+            BuildError::CapacityOverflow => write!(f, "trie capacity overflow"),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        hash, prehash, simple_hash, skip_list::SkipList, sorted, Cursor, Oneshot, TryOneshot,
+    };
+
+    use super::{Arena, BuildError};
+
+    /// Builds a small `T`, then checks that once the arena can't grow, a
+    /// relation which won't fit is rejected.
+    fn rejects_when_full<'b, T: TryOneshot<'b, 2, Value = i32>>(a: &'b Arena) {
+        let small = a.try_from_iter::<T, _, 2>((0..10).map(|x| [x, x])).unwrap();
+        assert_eq!(small.trie.open().len(), 10);
+
+        a.set_budget(Some(a.allocated()));
+        assert!(matches!(
+            a.try_from_iter::<T, _, 2>((0..10_000).map(|x| [x, x])),
+            Err(BuildError::OverBudget { .. })
+        ));
+    }
+
+    #[test]
+    fn budget() {
+        let a = Arena::with_budget(64 * 1024);
+
+        let small = a
+            .try_from_iter::<sorted::Trie<i32>, _, 2>((0..100).map(|x| [x % 10, x]))
+            .unwrap();
+        assert!(small.bytes > 0 && small.bytes <= a.used());
+        assert_eq!(Oneshot::<2>::open(&small.trie).len(), 10);
+
+        // Too big for what's left: rejected, with the arena still usable
+        let big = a.try_from_iter::<SkipList<i32, 2>, _, 2>((0..100_000).map(|x| [x, x]));
+        match big {
+            Err(BuildError::OverBudget { budget, allocated }) => {
+                assert_eq!(budget, 64 * 1024);
+                assert!(allocated <= budget);
+            }
+            _ => panic!("built a trie over budget"),
+        }
+
+        // The hash tries' tables count against the budget too
+        let big = (0..100_000).map(|x| [x, x]);
+        assert!(matches!(
+            a.try_from_iter::<simple_hash::Trie<i32, 2>, _, 2>(big.clone()),
+            Err(BuildError::OverBudget { .. })
+        ));
+        assert!(matches!(
+            a.try_from_iter::<hash::Trie<i32, 2>, _, 2>(big),
+            Err(BuildError::OverBudget { .. })
+        ));
+        assert!(a
+            .try_from_iter::<sorted::flat::Trie<i32, 1, sorted::flat::Read>, _, 1>([[1], [2]])
+            .is_ok());
+    }
+
+    #[test]
+    fn fallible() {
+        rejects_when_full::<hash::Trie<i32, 2>>(&Arena::new());
+        rejects_when_full::<simple_hash::Trie<i32, 2>>(&Arena::new());
+        rejects_when_full::<prehash::Trie<i32, 2>>(&Arena::new());
+        rejects_when_full::<SkipList<i32, 2>>(&Arena::new());
+        rejects_when_full::<sorted::Trie<i32>>(&Arena::new());
+        rejects_when_full::<sorted::flat::Trie<i32, 2, sorted::flat::Read>>(&Arena::new());
+
+        // The dynamic-arity tries too
+        let a = Arena::new();
+        a.set_budget(Some(a.allocated()));
+        let big = (0..10_000).map(|x| [x, x]);
+        assert!(a
+            .try_build(|b| hash::DynTrie::<i32>::try_from_iter(2, big.clone(), b))
+            .is_err());
+        assert!(a
+            .try_build(|b| sorted::DynTrie::<i32>::try_from_iter(2, big, b))
+            .is_err());
+    }
+
+    #[test]
+    fn unbounded() {
+        let a = Arena::new();
+        assert_eq!(a.budget(), None);
+
+        // Any builder can use the arena as a plain `Bump`
+        let t: sorted::Trie<i32> = Oneshot::<2>::from_iter((0..1000).map(|x| [x, x]), &a);
+        assert_eq!(Oneshot::<2>::open(&t).len(), 1000);
+        assert!(a.used() <= a.allocated());
+        assert!(BuildError::CapacityOverflow
+            .to_string()
+            .contains("overflow"));
+    }
+}
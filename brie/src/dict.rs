@@ -0,0 +1,182 @@
+//! Dictionary encoding, for joining on large or non-integer values.
+//!
+//! Every trie clones and compares its keys at every level, which is slow
+//! for `String`s and the like. A [`Dictionary`] interns each distinct value
+//! once into the arena and hands out a dense `u32` code for it; tries are
+//! then built and joined over codes, and only the results are decoded.
+
+use std::{
+    borrow::Borrow,
+    hash::{BuildHasher, Hash},
+};
+
+use bumpalo::Bump;
+use hashbrown::{hash_map::DefaultHashBuilder, raw::RawTable, BumpWrapper};
+
+use crate::{sorted::vec::BumpVec, Oneshot};
+
+/// Interns values of type `T` into dense `u32` codes, in insertion order.
+///
+/// Values live in the arena and are dropped along with the dictionary, so
+/// decoded references borrow the dictionary.
+pub struct Dictionary<'bump, T, B = DefaultHashBuilder> {
+    // Codes, hashed by the values they stand for
+    codes: RawTable<u32, BumpWrapper<'bump>>,
+    values: BumpVec<'bump, T>,
+    hasher: B,
+}
+
+impl<'bump, T, B> Dictionary<'bump, T, B>
+where
+    T: Eq + Hash,
+    B: BuildHasher,
+{
+    pub fn new_in(bump: &'bump Bump) -> Self
+    where
+        B: Default,
+    {
+        Self::with_hasher_in(B::default(), bump)
+    }
+
+    pub fn with_hasher_in(hasher: B, bump: &'bump Bump) -> Self {
+        Self {
+            codes: RawTable::new_in(BumpWrapper(bump)),
+            values: BumpVec::new(),
+            hasher,
+        }
+    }
+
+    /// The number of distinct values interned so far.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.len() == 0
+    }
+
+    /// Returns the code for `v`, interning it if it's new.
+    pub fn encode(&mut self, v: T, bump: &'bump Bump) -> u32 {
+        let hash = self.hasher.hash_one(&v);
+        if let Some(code) = self.find(hash, &v) {
+            return code;
+        }
+
+        let code = u32::try_from(self.values.len()).expect("dictionary is full");
+        self.values.push(v, bump);
+        let (values, hasher) = (&self.values, &self.hasher);
+        self.codes
+            .insert(hash, code, |&c| hasher.hash_one(&values[c as usize]));
+        code
+    }
+
+    /// Returns the code for `v` without interning it, e.g. for a query
+    /// constant which can't match anything if it was never encoded.
+    #[inline]
+    pub fn get(&self, v: &T) -> Option<u32> {
+        self.find(self.hasher.hash_one(v), v)
+    }
+
+    #[inline]
+    fn find(&self, hash: u64, v: &T) -> Option<u32> {
+        self.codes
+            .get(hash, |&c| &self.values[c as usize] == v)
+            .copied()
+    }
+
+    /// Returns the value for `code`.
+    ///
+    /// Panics if `code` wasn't handed out by this dictionary.
+    #[inline]
+    pub fn decode(&self, code: u32) -> &T {
+        &self.values[code as usize]
+    }
+
+    pub fn encode_tuple<const N: usize>(&mut self, tuple: [T; N], bump: &'bump Bump) -> [u32; N] {
+        tuple.map(|v| self.encode(v, bump))
+    }
+
+    pub fn decode_tuple<const N: usize>(&self, tuple: [u32; N]) -> [&T; N] {
+        tuple.map(|c| self.decode(c))
+    }
+
+    /// Decodes an iterator of codes, such as the keys yielded by
+    /// [`Oneshot::intersect`] on a trie built by [`Dictionary::build`].
+    pub fn decode_all<'a, I>(&'a self, codes: I) -> impl Iterator<Item = &'a T> + 'a
+    where
+        I: IntoIterator + 'a,
+        I::Item: Borrow<u32>,
+    {
+        codes.into_iter().map(|c| self.decode(*c.borrow()))
+    }
+
+    /// Encodes every tuple and builds a trie over the codes.
+    pub fn build<O, I, const N: usize>(&mut self, iter: I, bump: &'bump Bump) -> O
+    where
+        O: Oneshot<'bump, N, Value = u32>,
+        I: IntoIterator<Item = [T; N]>,
+    {
+        let encoded = iter
+            .into_iter()
+            .map(|t| self.encode_tuple(t, bump))
+            .collect::<Vec<_>>();
+        O::from_iter(encoded, bump)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use bumpalo::Bump;
+
+    use crate::{sorted, Oneshot};
+
+    use super::Dictionary;
+
+    #[test]
+    fn round_trip() {
+        let a = Bump::new();
+        let mut d: Dictionary<String> = Dictionary::new_in(&a);
+
+        let x = d.encode("x".to_string(), &a);
+        let y = d.encode("y".to_string(), &a);
+        assert_eq!(d.encode("x".to_string(), &a), x);
+        assert_eq!(d.len(), 2);
+        assert_eq!(d.get(&"y".to_string()), Some(y));
+        assert_eq!(d.get(&"z".to_string()), None);
+        assert_eq!(d.decode_tuple([y, x]), [&"y".to_string(), &"x".to_string()]);
+    }
+
+    #[test]
+    fn drops() {
+        let a = Bump::new();
+        let rc = Rc::new(());
+        let mut d: Dictionary<(i32, Rc<()>)> = Dictionary::new_in(&a);
+        for x in [0, 1, 0, 2] {
+            d.encode((x, rc.clone()), &a);
+        }
+        assert_eq!((d.len(), Rc::strong_count(&rc)), (3, 4));
+
+        drop(d);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn join_strings() {
+        let a = Bump::new();
+        let mut d: Dictionary<&str> = Dictionary::new_in(&a);
+
+        let r: sorted::Trie<u32> = d.build([["bob"], ["alice"], ["carol"]], &a);
+        let s: sorted::Trie<u32> = d.build([["carol"], ["dave"], ["bob"]], &a);
+
+        let mut v: Vec<_> = d
+            .decode_all(<sorted::Trie<u32> as Oneshot<1>>::intersect(&r, [&s]))
+            .copied()
+            .collect();
+        v.sort_unstable();
+        assert_eq!(v, vec!["bob", "carol"]);
+    }
+}
@@ -1,127 +1,275 @@
 //! Experimental flattened hash trie impl
 
 use std::{
-    hash::{Hash, Hasher},
+    hash::{BuildHasher, BuildHasherDefault, Hash, Hasher},
     iter::{self, FusedIterator},
-    marker::PhantomData,
-    mem::MaybeUninit,
+    mem,
+    ops::Deref,
 };
 
 use bumpalo::Bump;
-use itertools::Itertools;
 
-use crate::{sorted::vec::BumpVec, Oneshot};
+use crate::{
+    sorted::vec::{BumpVec, CollectionAllocError},
+    stats::{ChainStats, Stats, TrieStats},
+    store::Storable,
+    DynOneshot, HashOneshot, IntoIterType, Oneshot, TryOneshot,
+};
 
 // returns (cap, total_bits, hash_bits)
-fn get_bit_sizes<const N: usize>(iter_len: usize) -> (usize, u32, u32) {
-    let iter_len = iter_len * N;
+fn get_bit_sizes(arity: usize, iter_len: usize) -> (usize, u32, u32) {
+    let iter_len = iter_len * arity;
     let (hash_bits, hash_cap) = {
         let size = iter_len as f64;
         let upsize = (size * 1.25).ceil() as usize;
         let v = upsize.next_power_of_two();
-        (v.checked_log2().unwrap(), v)
+        (v.checked_ilog2().unwrap(), v)
     };
     let lvl_bits = {
-        let v = N.next_power_of_two();
-        v.checked_log2().unwrap()
+        let v = arity.next_power_of_two();
+        v.checked_ilog2().unwrap()
     };
     let (capacity, total_bits) = (hash_cap << lvl_bits, hash_bits + lvl_bits);
 
     (capacity, total_bits, hash_bits)
 }
 
-/// A dumb Trie that also manages query advancing state
-/// only for benching purposes!
-pub struct ManagedTrie<'bump, T, const N: usize> {
-    query: Vec<T>,
-    trie: Trie<'bump, T, N>,
+/// A flattened hash trie over `N`-tuples.
+///
+/// This is a [`DynTrie`] whose arity is fixed at compile time, so that
+/// tuples can be handed back as arrays; it derefs to the [`DynTrie`].
+pub struct Trie<'bump, T, const N: usize, B = BuildHasherDefault<ahash::AHasher>>(
+    DynTrie<'bump, T, B>,
+);
+
+impl<'bump, T, const N: usize, B> Trie<'bump, T, N, B>
+where
+    T: Clone + Hash + Default + PartialEq + Eq + Ord + std::fmt::Debug,
+    B: BuildHasher,
+{
+    pub fn from_sorted<I>(iter: I, bump: &'bump Bump) -> Self
+    where
+        I: IntoIterator<Item = [T; N]>,
+        B: Default,
+    {
+        Self::from_sorted_with_hasher(iter, B::default(), bump)
+    }
+
+    pub fn from_sorted_with_hasher<I>(iter: I, hasher: B, bump: &'bump Bump) -> Self
+    where
+        I: IntoIterator<Item = [T; N]>,
+    {
+        Self(DynTrie::from_sorted_with_hasher(N, iter, hasher, bump))
+    }
+
+    // Return iterator
+    // Assumes Ix exists in arrays
+    pub fn materialize_unchecked<'a, 't>(
+        &'t self,
+        query: &'a [T],
+        ix: Ix,
+    ) -> impl Iterator<Item = &'t [T; N]> + 'a
+    where
+        't: 'a,
+    {
+        self.0
+            .materialize_unchecked(query, ix)
+            .map(|t| t.try_into().expect("tuple has the trie's arity"))
+    }
+
+    /// See [`DynTrie::intersect_unchecked`].
+    pub fn intersect_unchecked<'t, const M: usize>(
+        &'t self,
+        from: Ix,
+        others: [(&'t Trie<'bump, T, N, B>, Ix); M],
+    ) -> impl Iterator<Item = (&'t T, Ix, [Ix; M])> + 't {
+        self.0
+            .intersect_unchecked(from, others.map(|(t, ix)| (&t.0, ix)))
+    }
 }
 
-impl<'b, T: Clone + Hash + Ord + Eq + Default + std::fmt::Debug, const N: usize> Oneshot<'b, N>
-    for ManagedTrie<'b, T, N>
+impl<'b, T, const N: usize, B> Oneshot<'b, N> for Trie<'b, T, N, B>
+where
+    T: Clone + Hash + Ord + Eq + Default + std::fmt::Debug + 'b,
+    B: BuildHasher + Default + 'b,
 {
     type Value = T;
     type IVal = &'b T;
-    type KeyIter<const M: usize> = impl Iterator<Item = &'b T>;
+    type KeyIter<const M: usize>
+        = iter_type!('b, &'b T)
+    where
+        Self: 'b;
+    type Cursor<'a>
+        = Cursor<'a, 'b, T, B>
+    where
+        Self: 'a;
 
     fn from_iter<I: IntoIterator<Item = [Self::Value; N]>>(iter: I, bump: &'b Bump) -> Self {
+        Self::from_iter_with_hasher(iter, B::default(), bump)
+    }
+
+    fn open(&self) -> Self::Cursor<'_> {
+        Cursor::root(&self.0)
+    }
+
+    fn intersect<const M: usize>(&'b self, others: [&'b Self; M]) -> Self::KeyIter<M> {
+        self.intersect_unchecked(Ix::none(), others.map(|t| (t, Ix::none())))
+            .map(|x| x.0)
+            .into_iter_type()
+    }
+}
+
+impl<'b, T, const N: usize, B> HashOneshot<'b, N> for Trie<'b, T, N, B>
+where
+    T: Clone + Hash + Ord + Eq + Default + std::fmt::Debug + 'b,
+    B: BuildHasher + Default + 'b,
+{
+    type BuildHasher = B;
+
+    fn from_iter_with_hasher<I: IntoIterator<Item = [T; N]>>(
+        iter: I,
+        hasher: B,
+        bump: &'b Bump,
+    ) -> Self {
         let mut elems = iter.into_iter().collect::<Vec<_>>();
         elems.sort_unstable();
-        Self {
-            query: Vec::new(),
-            trie: Trie::from_sorted::<ahash::AHasher, _>(elems.into_iter(), bump).unwrap(),
-        }
+        Self::from_sorted_with_hasher(elems, hasher, bump)
     }
+}
 
-    fn advance(&'b self, v: &Self::Value) -> Option<&'b Self> {
-        todo!()
-        // self.query.push(v.clone());
-
-        // Some(self)
+impl<'b, T, const N: usize, B> TryOneshot<'b, N> for Trie<'b, T, N, B>
+where
+    T: Clone + Hash + Ord + Eq + Default + std::fmt::Debug + 'b,
+    B: BuildHasher + Default + 'b,
+{
+    fn try_from_iter<I: IntoIterator<Item = [T; N]>>(
+        iter: I,
+        bump: &'b Bump,
+    ) -> Result<Self, CollectionAllocError> {
+        let mut elems = iter.into_iter().collect::<Vec<_>>();
+        elems.sort_unstable();
+        DynTrie::try_from_sorted_with_hasher(N, elems, B::default(), bump).map(Self)
     }
+}
 
-    fn intersect<'a, const M: usize>(&'b self, others: [&'b Self; M]) -> Self::KeyIter<M> {
-        let from = self.trie.query_to_ix::<ahash::AHasher>(self.query.as_ref());
-        let others: [(&'b Trie<_, N>, Ix); M] = unsafe {
-            let mut arr: [_; M] = MaybeUninit::uninit().assume_init();
-            for (item, mt) in (&mut arr[..]).into_iter().zip(others) {
-                std::ptr::write(
-                    item,
-                    (
-                        &mt.trie,
-                        mt.trie.query_to_ix::<ahash::AHasher>(self.query.as_ref()),
-                    ),
-                );
-            }
-            arr
-        };
+impl<'bump, T, const N: usize, B> Deref for Trie<'bump, T, N, B> {
+    type Target = DynTrie<'bump, T, B>;
 
-        self.trie
-            .intersect_unchecked::<ahash::AHasher, M>(from, others)
-            .map(|x| x.0)
+    fn deref(&self) -> &Self::Target {
+        &self.0
     }
 }
 
-pub struct Trie<'bump, T, const N: usize> {
+/// A flattened hash trie whose arity is only known at runtime.
+///
+/// `B` hashes (parent, key) pairs into `hash_keys`; tries can only be
+/// intersected if they were built with the same hasher.
+pub struct DynTrie<'bump, T, B = BuildHasherDefault<ahash::AHasher>> {
+    arity: usize,
     hash_keys: BumpVec<'bump, Key<T>>,
     extra_sibs: BumpVec<'bump, Key<T>>,
     pub root: Ix,
-    data: BumpVec<'bump, [T; N]>,
+    /// Every tuple back to back; tuple `i` is `data[i * arity..][..arity]`.
+    data: BumpVec<'bump, T>,
+    hash_bits: u32,
+    hasher: B,
 }
 
-impl<'bump, T, const N: usize> Trie<'bump, T, N>
+impl<'bump, T, B> DynTrie<'bump, T, B>
 where
     T: Clone + Hash + Default + PartialEq + Eq + Ord + std::fmt::Debug,
+    B: BuildHasher,
 {
-    pub fn from_sorted<H, I>(iter: I, bump: &'bump Bump) -> Option<Self>
+    pub fn from_sorted<I, R>(arity: usize, iter: I, bump: &'bump Bump) -> Self
     where
-        I: IntoIterator<Item = [T; N]>,
-        H: Hasher + Default,
+        I: IntoIterator<Item = R>,
+        R: AsRef<[T]>,
+        B: Default,
+    {
+        Self::from_sorted_with_hasher(arity, iter, B::default(), bump)
+    }
+
+    /// Builds a trie from tuples in sorted order. The table is sized from
+    /// `iter`'s size hint if it's exact, and otherwise the tuples are
+    /// gathered up and counted first.
+    pub fn from_sorted_with_hasher<I, R>(
+        arity: usize,
+        iter: I,
+        hasher: B,
+        bump: &'bump Bump,
+    ) -> Self
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[T]>,
+    {
+        Self::try_from_sorted_with_hasher(arity, iter, hasher, bump).unwrap_or_else(|e| e.raise())
+    }
+
+    /// Like [`DynTrie::from_sorted_with_hasher`], but fails instead of
+    /// panicking if the arena can't hold the table and tuples.
+    pub fn try_from_sorted_with_hasher<I, R>(
+        arity: usize,
+        iter: I,
+        hasher: B,
+        bump: &'bump Bump,
+    ) -> Result<Self, CollectionAllocError>
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[T]>,
     {
         let iter = iter.into_iter();
-        let iter_len = if let Some(ub) = iter.size_hint().1 {
-            if iter.size_hint().0 > 0 {
-                ub
-            } else {
-                return None;
+        match iter.size_hint() {
+            (lo, Some(hi)) if lo == hi => Self::build(arity, lo, iter, hasher, bump),
+            _ => {
+                let tuples = iter.collect::<Vec<_>>();
+                Self::build(arity, tuples.len(), tuples, hasher, bump)
             }
-        } else {
-            return None;
-        };
+        }
+    }
 
-        let (capacity, _total_bits, hash_bits) = get_bit_sizes::<N>(iter_len);
-        let mut hash_keys = BumpVec::with_capacity_in(capacity, bump);
-        let mut extra_sibs: BumpVec<'bump, Key<T>> = BumpVec::with_capacity_in(capacity, bump);
-        let mut data = BumpVec::with_capacity_in(capacity, bump);
+    /// The fallible counterpart of [`DynOneshot::from_iter`].
+    pub fn try_from_iter<I, R>(
+        arity: usize,
+        iter: I,
+        bump: &'bump Bump,
+    ) -> Result<Self, CollectionAllocError>
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[T]>,
+        B: Default,
+    {
+        let mut tuples = iter.into_iter().collect::<Vec<_>>();
+        tuples.sort_unstable_by(|x, y| x.as_ref().cmp(y.as_ref()));
+        Self::try_from_sorted_with_hasher(
+            arity,
+            tuples.iter().map(|t| t.as_ref()),
+            B::default(),
+            bump,
+        )
+    }
+
+    /// Builds the trie in a table sized for `iter_len` tuples. Every array
+    /// is allocated up front, so filling them in won't allocate again.
+    fn build<I, R>(
+        arity: usize,
+        iter_len: usize,
+        iter: I,
+        hasher: B,
+        bump: &'bump Bump,
+    ) -> Result<Self, CollectionAllocError>
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[T]>,
+    {
+        let (capacity, _total_bits, hash_bits) = get_bit_sizes(arity, iter_len);
+        let mut hash_keys = BumpVec::try_with_capacity_in(capacity, bump)?;
+        // Every key of every tuple fits in the table, so there can't be more
+        // sibs than that
+        let mut extra_sibs: BumpVec<'bump, Key<T>> = BumpVec::try_with_capacity_in(capacity, bump)?;
+        let mut data = BumpVec::try_with_capacity_in(iter_len * arity, bump)?;
         let mut root = Ix::none();
 
-        let mut cur_sibs: [(T, Ix); N] = unsafe {
-            let mut arr: [_; N] = MaybeUninit::uninit().assume_init();
-            for item in &mut arr[..] {
-                std::ptr::write(item, Default::default());
-            }
-            arr
-        };
+        let mut cur_sibs: Vec<(T, Ix)> = vec![Default::default(); arity];
 
         // Zero-initialize keys
         for _i in 0..capacity {
@@ -129,63 +277,77 @@ where
         }
 
         for v in iter {
+            let v = v.as_ref();
+            assert_eq!(v.len(), arity, "tuple has the wrong arity");
+
             // For each tuple of values we need to create corresponding entries in the
             // keys list!
             let mut cur_ix = Ix::none();
             let mut sib_set = false;
 
             for (level, t) in v.iter().enumerate() {
-                let ix = Self::calc_hash_keys_ix::<H>(cur_ix, t, level, hash_bits);
+                let ix = Self::calc_hash_keys_ix(&hasher, cur_ix, t, level, hash_bits);
                 if root.is_none() {
                     root = Ix::hashed(ix);
                 }
 
                 // Step 1
-                // Find what key block this should correspond to
+                // Find what key block this should correspond to: the hashed
+                // slot if it's free, or whichever block in its chain of hash
+                // sibs already holds this key. If none of them do, we'll
+                // need a new sib at the end of the chain.
+                let mut existing = None;
                 let mut new_key: Result<usize, (Result<usize, Sibling>, usize)> = Ok(ix);
                 let b = &hash_keys[ix];
-                if !b.child.is_none() && (b.parent_ix != cur_ix || &b.data != t) {
-                    let fst = if b.hash_sib.is_none() {
-                        Ok(ix)
+                if !b.child.is_none() {
+                    if b.parent_ix == cur_ix && &b.data == t {
+                        existing = Some(Ix::hashed(ix));
                     } else {
+                        let mut last = Ok(ix);
                         let mut z = b.hash_sib;
-                        loop {
-                            let zp = extra_sibs[z.0].hash_sib;
-                            if zp.is_none() {
+                        while !z.is_none() {
+                            let k = &extra_sibs[z.0];
+                            if k.parent_ix == cur_ix && &k.data == t {
+                                existing = Some(Ix::sibbed(z.0));
                                 break;
-                            } else {
-                                z = zp;
                             }
+                            last = Err(z);
+                            z = k.hash_sib;
                         }
-                        Err(z)
-                    };
 
-                    let new_ix = extra_sibs.len();
-                    new_key = Err((fst, new_ix));
+                        let new_ix = extra_sibs.len();
+                        new_key = Err((last, new_ix));
+                    }
                 }
 
                 // Step 2
                 // We have our key block.
+                // If we already had it, there's nothing to set up.
                 // If we didn't need a sib, set the params appropriately
                 // If we did need a sib, set the previous sibs attributes appropriately
-                match new_key {
-                    Ok(hash_ix) => {
+                // Either way, a new block is its parent's child only if it's
+                // the first one: children are walked from there by tuple sib.
+                match (existing, new_key) {
+                    (Some(existing), _) => {
+                        cur_ix = existing;
+                    }
+                    (None, Ok(hash_ix)) => {
                         let new_ix = Ix::hashed(hash_ix);
 
                         let this = &mut hash_keys[hash_ix];
                         this.data = t.clone();
                         this.parent_ix = cur_ix;
 
-                        if cur_ix.is_hashed() {
-                            hash_keys[cur_ix.0].child = Child::hashed(hash_ix);
-                        } else if !cur_ix.is_none() {
-                            extra_sibs[cur_ix.0 % (1 << (usize::BITS - 1))].child =
-                                Child::hashed(hash_ix);
+                        if let Some(parent) = Self::key_mut(&mut hash_keys, &mut extra_sibs, cur_ix)
+                        {
+                            if parent.child.is_none() {
+                                parent.child = Child::hashed(hash_ix);
+                            }
                         }
 
                         cur_ix = new_ix;
                     }
-                    Err((sib_at, new_ix)) => {
+                    (None, Err((sib_at, new_ix))) => {
                         extra_sibs.push(
                             Key {
                                 data: t.clone(),
@@ -200,11 +362,11 @@ where
                         };
                         prev.hash_sib = Sibling::sibbed(new_ix);
 
-                        if cur_ix.is_hashed() {
-                            hash_keys[cur_ix.0].child = Child::sibbed(new_ix);
-                        } else if !cur_ix.is_none() {
-                            extra_sibs[cur_ix.0 % (1 << (usize::BITS - 1))].child =
-                                Child::sibbed(new_ix);
+                        if let Some(parent) = Self::key_mut(&mut hash_keys, &mut extra_sibs, cur_ix)
+                        {
+                            if parent.child.is_none() {
+                                parent.child = Child::sibbed(new_ix);
+                            }
                         }
 
                         cur_ix = Ix::sibbed(new_ix);
@@ -228,27 +390,116 @@ where
                 cur_sibs[level] = (t.clone(), cur_ix);
             }
 
-            data.push(v, bump);
+            for t in v {
+                data.push(t.clone(), bump);
+            }
+            let data_ix = data.len() / arity - 1;
             match cur_ix.as_enum().unwrap() {
                 Ok(ix) => {
-                    hash_keys[ix].child = Child::data(data.len() - 1);
+                    hash_keys[ix].child = Child::data(data_ix);
                 }
                 Err(ix) => {
-                    extra_sibs[ix].child = Child::data(data.len() - 1);
+                    extra_sibs[ix].child = Child::data(data_ix);
                 }
             }
         }
 
-        Some(Self {
+        Ok(Self {
+            arity,
             root,
             hash_keys,
             extra_sibs,
             data,
+            hash_bits,
+            hasher,
         })
     }
 
+    /// The number of values in each tuple.
+    #[inline]
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// The number of tuples stored.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len() / self.arity
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The hasher this trie was built with; build other tries with a clone
+    /// of it to intersect against this one.
+    #[inline]
+    pub fn hasher(&self) -> &B {
+        &self.hasher
+    }
+
+    /// The key block at `ix`, if it isn't none.
+    #[inline]
+    fn key(&self, ix: Ix) -> Option<&Key<T>> {
+        if ix.is_none() {
+            None
+        } else if ix.is_hashed() {
+            Some(&self.hash_keys[ix.0])
+        } else {
+            Some(&self.extra_sibs[ix.0 % (1 << (usize::BITS - 1))])
+        }
+    }
+
+    #[inline]
+    fn key_mut<'k>(
+        hash_keys: &'k mut BumpVec<'bump, Key<T>>,
+        extra_sibs: &'k mut BumpVec<'bump, Key<T>>,
+        ix: Ix,
+    ) -> Option<&'k mut Key<T>> {
+        if ix.is_none() {
+            None
+        } else if ix.is_hashed() {
+            Some(&mut hash_keys[ix.0])
+        } else {
+            Some(&mut extra_sibs[ix.0 % (1 << (usize::BITS - 1))])
+        }
+    }
+
+    /// The first child of the key at `ix`, from which the rest of its
+    /// children can be walked by tuple sib. The children of none are the
+    /// first level, and leaves have no children.
+    #[inline]
+    fn first_child(&self, ix: Ix) -> Ix {
+        match self.key(ix) {
+            None => self.root,
+            Some(k) => k.child.as_ix().unwrap_or_else(Ix::none),
+        }
+    }
+
+    /// Looks up the child of `parent` holding `value` by hash.
+    fn find_child(&self, parent: Ix, value: &T, level: usize) -> Option<Ix> {
+        let hk = Self::calc_hash_keys_ix(&self.hasher, parent, value, level, self.hash_bits);
+        let mut cur = Ix::hashed(hk);
+
+        // Look at block.
+        // If parent value matches and key value matches, we're good
+        // Otherwise, go to hash sib
+        loop {
+            let block = self.key(cur)?;
+            if block.child.is_none() {
+                // Unoccupied slot
+                return None;
+            } else if block.parent_ix == parent && &block.data == value {
+                return Some(cur);
+            } else {
+                cur = block.hash_sib.as_ix();
+            }
+        }
+    }
+
     // Assumes Ix is valid
-    fn get_data_ix_unchecked<'a>(&self, ix: Ix) -> usize {
+    fn get_data_ix_unchecked(&self, ix: Ix) -> usize {
         // Our backing array is in sorted order so all we need to do is actually find the
         // correct start to begin with.
         // Basically, we go to hash_ix in the hash_keys array.
@@ -289,20 +540,42 @@ where
         }
     }
 
+    /// The number of tuples under the key at `ix`, or in the whole trie if
+    /// it's none. Tuples are stored in order, so they run from the key's
+    /// own first tuple up to that of the next key along, which is the tuple
+    /// sib of the key or of its nearest ancestor to have one.
+    fn tuples_under(&self, ix: Ix) -> usize {
+        let mut up = match self.key(ix) {
+            Some(k) => k,
+            None => return self.len(),
+        };
+        let start = self.get_data_ix_unchecked(ix);
+        let end = loop {
+            if !up.tuple_sib.is_none() {
+                break self.get_data_ix_unchecked(up.tuple_sib);
+            }
+            match self.key(up.parent_ix) {
+                Some(parent) => up = parent,
+                None => break self.len(),
+            }
+        };
+        end - start
+    }
+
     // Return iterator
     // Assumes Ix exists in arrays
     pub fn materialize_unchecked<'a, 't>(
         &'t self,
         query: &'a [T],
         ix: Ix,
-    ) -> Materialize<'a, 'bump, 't, T, N> {
+    ) -> Materialize<'a, 't, T> {
         if ix.is_none() {
             Materialize {
                 query: &[],
                 data: &self.data,
+                arity: self.arity,
                 idx: 0,
-                end: self.data.len(),
-                _marker: PhantomData,
+                end: self.len(),
             }
         } else {
             let idx = self.get_data_ix_unchecked(ix);
@@ -310,9 +583,9 @@ where
             Materialize {
                 query,
                 data: &self.data,
+                arity: self.arity,
                 idx,
-                end: self.data.len(),
-                _marker: PhantomData,
+                end: self.len(),
             }
         }
     }
@@ -320,44 +593,18 @@ where
     /// Intersects the keys of M + 1 tries given a query.
     /// Performs this by materializing all tries and going through the elements
     /// of all tries at once, finding points where the keys match up.
+    /// Yields each shared key with the Ix of its block in this trie and in
+    /// each of the others, which can be passed back in as `from`.
     /// Assumes Ix is valid
-    pub fn intersect_unchecked<'a, 't: 'bump, H: Hasher + Default, const M: usize>(
+    pub fn intersect_unchecked<'t, const M: usize>(
         &'t self,
         from: Ix,
-        mut others: [(&'t Trie<'bump, T, N>, Ix); M],
-    ) -> impl Iterator<Item = (&'bump T, Ix)> + 'a
-    where
-        'bump: 'a,
-        't: 'a,
-    {
-        let mut cur_ix = if from.is_none() {
-            self.root
-        } else if from.is_hashed() {
-            self.hash_keys[from.0]
-                .child
-                .as_ix()
-                .expect("can't use data ix")
-        } else {
-            self.extra_sibs[from.0 % (1 << (usize::BITS - 1))]
-                .child
-                .as_ix()
-                .expect("can't use data ix")
-        };
+        mut others: [(&'t DynTrie<'bump, T, B>, Ix); M],
+    ) -> impl Iterator<Item = (&'t T, Ix, [Ix; M])> + 't {
+        let mut cur_ix = self.first_child(from);
 
         for (other_trie, other_ix) in others.iter_mut() {
-            *other_ix = if other_ix.is_none() {
-                other_trie.root
-            } else if other_ix.is_hashed() {
-                other_trie.hash_keys[other_ix.0]
-                    .child
-                    .as_ix()
-                    .expect("can't use data ix")
-            } else {
-                other_trie.extra_sibs[other_ix.0 % (1 << (usize::BITS - 1))]
-                    .child
-                    .as_ix()
-                    .expect("can't use data ix")
-            };
+            *other_ix = other_trie.first_child(*other_ix);
         }
 
         let mut cur_max = None;
@@ -381,9 +628,10 @@ where
                 }
 
                 cur_max = Some(&xk.data);
+                let mut matched = [Ix::none(); M];
 
                 // For each of the other tries
-                for (other_trie, other_ix) in others.iter_mut() {
+                for ((other_trie, other_ix), matched) in others.iter_mut().zip(matched.iter_mut()) {
                     'inner: loop {
                         let yk = if other_ix.is_hashed() {
                             &other_trie.hash_keys[other_ix.0]
@@ -400,11 +648,12 @@ where
                             }
                         }
 
-                        if &yk.data > &xk.data {
+                        if yk.data > xk.data {
                             cur_max = Some(&yk.data);
                             // Advancing xk taken care of above
                             continue 'outer;
-                        } else if &yk.data == &xk.data {
+                        } else if yk.data == xk.data {
+                            *matched = *other_ix;
                             *other_ix = yk.tuple_sib;
                             break 'inner;
                         } else {
@@ -415,7 +664,7 @@ where
                 }
 
                 // We've passed the gauntlet
-                let res = Some((&xk.data, cur_ix));
+                let res = Some((&xk.data, cur_ix, matched));
                 // Advance xk
                 cur_ix = xk.tuple_sib;
                 return res;
@@ -424,13 +673,8 @@ where
         .fuse()
     }
 
-    fn calc_hash_keys_ix<H: Hasher + Default>(
-        prev: Ix,
-        value: &T,
-        level: usize,
-        hash_bits: u32,
-    ) -> usize {
-        let mut hasher = H::default();
+    fn calc_hash_keys_ix(hasher: &B, prev: Ix, value: &T, level: usize, hash_bits: u32) -> usize {
+        let mut hasher = hasher.build_hasher();
         prev.hash(&mut hasher);
         value.hash(&mut hasher);
 
@@ -458,31 +702,13 @@ where
         ix
     }
 
-    pub fn query_to_ix<H: Hasher + Default>(&self, query: &[T]) -> Ix {
-        let (_capacity, _total_bits, hash_bits) = get_bit_sizes::<N>(self.data.len());
+    pub fn query_to_ix(&self, query: &[T]) -> Ix {
         let mut cur = Ix::none();
 
         for (l, q) in query.iter().enumerate() {
-            let hk = Self::calc_hash_keys_ix::<H>(cur, q, l, hash_bits);
-            cur = Ix::hashed(hk);
-
-            // Look at block.
-            // If parent value matches and key value matches, we're good
-            // Otherwise, go to hash sib
-            loop {
-                let cur_block = if cur.is_none() {
-                    return Ix::none();
-                } else if cur.is_hashed() {
-                    &self.hash_keys[cur.0]
-                } else {
-                    &self.extra_sibs[cur.0 % (1 << (usize::BITS - 1))]
-                };
-
-                if cur_block.parent_ix == cur && &cur_block.data == q {
-                    break;
-                } else {
-                    cur = cur_block.hash_sib.as_ix();
-                }
+            match self.find_child(cur, q, l) {
+                Some(ix) => cur = ix,
+                None => return Ix::none(),
             }
         }
 
@@ -490,19 +716,158 @@ where
     }
 }
 
-pub struct Materialize<'a, 'b, 't, T, const N: usize> {
+impl<'bump, T, B> DynOneshot<'bump> for DynTrie<'bump, T, B>
+where
+    T: Clone + Hash + Default + Ord + std::fmt::Debug + 'bump,
+    B: BuildHasher + Default + 'bump,
+{
+    type Value = T;
+    type IVal = &'bump T;
+    type KeyIter<const M: usize>
+        = iter_type!('bump, &'bump T)
+    where
+        Self: 'bump;
+    type Cursor<'a>
+        = Cursor<'a, 'bump, T, B>
+    where
+        Self: 'a;
+
+    fn from_iter<I, R>(arity: usize, iter: I, bump: &'bump Bump) -> Self
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[T]>,
+    {
+        Self::try_from_iter(arity, iter, bump).unwrap_or_else(|e| e.raise())
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn open(&self) -> Self::Cursor<'_> {
+        Cursor::root(self)
+    }
+
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
+        self.intersect_unchecked(Ix::none(), others.map(|t| (t, Ix::none())))
+            .map(|x| x.0)
+            .into_iter_type()
+    }
+}
+
+/// A [`crate::Cursor`] into a [`DynTrie`]: the key block at `ix`, or the
+/// root if `ix` is none.
+pub struct Cursor<'a, 'bump, T, B> {
+    trie: &'a DynTrie<'bump, T, B>,
+    ix: Ix,
+    depth: usize,
+}
+
+impl<'a, 'bump, T, B> Cursor<'a, 'bump, T, B> {
+    fn root(trie: &'a DynTrie<'bump, T, B>) -> Self {
+        Self {
+            trie,
+            ix: Ix::none(),
+            depth: 0,
+        }
+    }
+
+    /// The key block this cursor is at, to pass to `intersect_unchecked`.
+    #[inline]
+    pub fn ix(&self) -> Ix {
+        self.ix
+    }
+}
+
+impl<'a, 'bump, T, B> Clone for Cursor<'a, 'bump, T, B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, 'bump, T, B> Copy for Cursor<'a, 'bump, T, B> {}
+
+impl<'a, 'bump: 'a, T, B> crate::Cursor<'a> for Cursor<'a, 'bump, T, B>
+where
+    T: Clone + Hash + Default + Ord + std::fmt::Debug + 'bump,
+    B: BuildHasher,
+{
+    type Value = T;
+    type Keys = iter_type!('a, &'a T);
+    type Intersect<const M: usize> = iter_type!('a, (&'a T, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn len(&self) -> usize {
+        self.keys().count()
+    }
+
+    /// The tuples under this cursor's key block.
+    fn len_hint(&self) -> usize {
+        if self.depth == self.trie.arity {
+            0
+        } else {
+            self.trie.tuples_under(self.ix)
+        }
+    }
+
+    fn keys(&self) -> Self::Keys {
+        let trie = self.trie;
+        let mut ix = trie.first_child(self.ix);
+        iter::from_fn(move || {
+            let k = trie.key(ix)?;
+            ix = k.tuple_sib;
+            Some(&k.data)
+        })
+        .fuse()
+        .into_iter_type()
+    }
+
+    fn advance(&self, v: &T) -> Option<Self> {
+        if self.depth == self.trie.arity {
+            return None;
+        }
+
+        Some(Self {
+            trie: self.trie,
+            ix: self.trie.find_child(self.ix, v, self.depth)?,
+            depth: self.depth + 1,
+        })
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        let this = *self;
+        // Participants may be at different depths of their tries
+        let child = |c: &Self, ix| Self {
+            ix,
+            depth: c.depth + 1,
+            ..*c
+        };
+
+        self.trie
+            .intersect_unchecked(self.ix, others.map(|c| (c.trie, c.ix)))
+            .map(move |(k, ix, matched)| {
+                let mut others = others.iter();
+                let children = matched.map(|ix| child(others.next().unwrap(), ix));
+                (k, child(&this, ix), children)
+            })
+            .into_iter_type()
+    }
+}
+
+pub struct Materialize<'a, 't, T> {
     query: &'a [T],
-    data: &'t BumpVec<'b, [T; N]>,
+    data: &'t [T],
+    arity: usize,
     idx: usize,
     end: usize,
-    _marker: PhantomData<&'b [T; N]>,
 }
 
-impl<'a, 'b, 't, T: PartialEq, const N: usize> Iterator for Materialize<'a, 'b, 't, T, N>
-where
-    't: 'b,
-{
-    type Item = &'b [T; N];
+impl<'a, 't, T: PartialEq> Iterator for Materialize<'a, 't, T> {
+    type Item = &'t [T];
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
@@ -513,16 +878,16 @@ where
             None
         } else {
             unsafe {
-                let idx = self.idx;
+                let start = self.idx * self.arity;
                 self.idx += 1;
-                Some(self.data.get_unchecked(idx))
+                Some(self.data.get_unchecked(start..start + self.arity))
             }
         }
     }
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let upper = if self.query.len() == N {
+        let upper = if self.query.len() == self.arity {
             Some(1)
         } else {
             Some(self.end - self.idx)
@@ -532,10 +897,7 @@ where
     }
 }
 
-impl<'a, 'b, 't, T: PartialEq, const N: usize> FusedIterator for Materialize<'a, 'b, 't, T, N> where
-    't: 'b
-{
-}
+impl<'a, 't, T: PartialEq> FusedIterator for Materialize<'a, 't, T> {}
 
 #[derive(Debug, Default, PartialEq, Eq)]
 struct Key<T> {
@@ -627,8 +989,8 @@ impl Child {
             Some(Ix(self.0))
         } else {
             let mut val = self.0;
-            val = val ^ (1 << (usize::BITS - 2));
-            val = val ^ (1 << (usize::BITS - 1));
+            val ^= 1 << (usize::BITS - 2);
+            val ^= 1 << (usize::BITS - 1);
             Some(Ix(val))
         }
     }
@@ -689,39 +1051,190 @@ impl Default for Ix {
     }
 }
 
+impl<T, B> Storable for DynTrie<'static, T, B> {
+    type Trie<'bump> = DynTrie<'bump, T, B>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'bump, T, B> Stats for DynTrie<'bump, T, B>
+where
+    T: Clone + Hash + Default + Ord + std::fmt::Debug + 'bump,
+    B: BuildHasher,
+{
+    fn stats(&self) -> TrieStats {
+        let mut chains = ChainStats {
+            slots: self.hash_keys.len(),
+            overflow: self.extra_sibs.len(),
+            ..ChainStats::default()
+        };
+        for k in self.hash_keys.iter().filter(|k| !k.child.is_none()) {
+            let mut len = 1;
+            let mut sib = k;
+            while let Some(next) = self.key(sib.hash_sib.as_ix()) {
+                sib = next;
+                len += 1;
+            }
+            chains.occupied += 1;
+            chains.max_len = chains.max_len.max(len);
+        }
+
+        let keys = self.hash_keys.capacity() + self.extra_sibs.capacity();
+        TrieStats {
+            chains: Some(chains),
+            bytes: keys * mem::size_of::<Key<T>>() + self.data.capacity() * mem::size_of::<T>(),
+            ..TrieStats::walk(Cursor::root(self), |_| (0, 0))
+        }
+    }
+}
+
+impl<T, const N: usize, B> Storable for Trie<'static, T, N, B> {
+    type Trie<'bump> = Trie<'bump, T, N, B>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'bump, T, const N: usize, B> Stats for Trie<'bump, T, N, B>
+where
+    T: Clone + Hash + Default + Ord + std::fmt::Debug + 'bump,
+    B: BuildHasher,
+{
+    fn stats(&self) -> TrieStats {
+        self.0.stats()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use bumpalo::Bump;
     use itertools::iproduct;
 
-    use super::{Ix, Trie};
+    use crate::{Cursor, DynOneshot, Oneshot};
+
+    use super::{DynTrie, Ix, Trie};
 
     #[test]
     fn iter_keys() {
         let a = Bump::new();
-        let t = Trie::from_sorted::<ahash::AHasher, _>((0..10).map(|x| [x]), &a).unwrap();
+        let t = Trie::<_, 1>::from_sorted((0..10).map(|x| [x]), &a);
 
         let v: Vec<_> = t
-            .intersect_unchecked::<ahash::AHasher, 0>(Ix::none(), [])
+            .intersect_unchecked::<0>(Ix::none(), [])
             .map(|x| *x.0)
             .collect();
         assert_eq!(v, (0..10).collect::<Vec<_>>());
     }
 
+    #[test]
+    fn filtered() {
+        // A filter has no lower size hint, so the trie counts its tuples
+        let a = Bump::new();
+        let iter = (0..100).filter(|x| x % 3 == 0).map(|x| [x / 10, x]);
+        let t = Trie::<_, 2>::from_sorted(iter, &a);
+
+        assert_eq!(t.len(), 34);
+        let keys: Vec<_> = t.open().keys().copied().collect();
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
+        let c = t.open().advance(&3).unwrap();
+        assert_eq!(c.keys().copied().collect::<Vec<_>>(), vec![30, 33, 36, 39]);
+    }
+
     #[test]
     fn iter_nested() {
         let sz = &10;
         let iter = iproduct!(0..*sz, 0..*sz, 0..*sz, 0..*sz, 0..*sz)
             .map(|(x, y, z, a, b)| [x, y, z, a, b]);
         let a = Bump::new();
-        let t = Trie::from_sorted::<ahash::AHasher, _>(iter, &a).unwrap();
-
-        println!("{:?}", t.hash_keys[t.root.0]);
+        let t = Trie::<_, 5>::from_sorted(iter, &a);
 
         let v: Vec<_> = t
-            .intersect_unchecked::<ahash::AHasher, 0>(Ix::none(), [])
+            .intersect_unchecked::<0>(Ix::none(), [])
             .map(|x| *x.0)
             .collect();
         assert_eq!(v, (0..10).collect::<Vec<_>>());
     }
+
+    #[test]
+    fn dyn_arity() {
+        let a = Bump::new();
+        let tuples = (0..10).map(|x| vec![x % 3, x]).collect::<Vec<_>>();
+        let t: DynTrie<i32> = DynOneshot::from_iter(2, &tuples, &a);
+        let t2: DynTrie<i32> = DynOneshot::from_iter(2, [[0, 0], [2, 1]], &a);
+
+        assert_eq!(t.len(), 10);
+        let v: Vec<_> = t.intersect([&t2]).copied().collect();
+        assert_eq!(v, vec![0, 2]);
+
+        let typed = Trie::<_, 2>::from_sorted([[0, 1], [0, 2], [1, 0]], &a);
+        let leaves: Vec<_> = typed
+            .materialize_unchecked(&[], Ix::none())
+            .copied()
+            .collect();
+        assert_eq!(leaves, vec![[0, 1], [0, 2], [1, 0]]);
+    }
+
+    #[test]
+    fn empty() {
+        let a = Bump::new();
+        let full: Trie<i32, 2> = Oneshot::from_iter((0..10).map(|x| [x % 3, x]), &a);
+        let t: Trie<i32, 2> = Oneshot::from_iter([], &a);
+        assert!(t.is_empty());
+        assert_eq!(t.open().keys().count(), 0);
+        assert!(t.open().advance(&0).is_none());
+        assert_eq!(full.intersect([&t]).count(), 0);
+        assert_eq!(t.intersect([&full]).count(), 0);
+
+        let d: DynTrie<i32> = DynOneshot::from_iter(3, Vec::<Vec<i32>>::new(), &a);
+        assert_eq!((d.len(), d.arity()), (0, 3));
+        assert_eq!(d.open().keys().count(), 0);
+        assert_eq!(d.intersect([&d]).count(), 0);
+    }
+
+    #[test]
+    fn cursor() {
+        let tuples: Vec<_> = iproduct!(0..8, 0..8, 0..8)
+            .filter(|(x, y, z)| (x * y + z) % 3 != 0)
+            .map(|(x, y, z)| [x, y, z])
+            .collect();
+        let a = Bump::new();
+        let t: Trie<_, 3> = Oneshot::from_iter(tuples.iter().copied().rev(), &a);
+
+        // Every node's keys are the distinct next values under its prefix
+        let root = t.open();
+        assert_eq!(
+            root.keys().copied().collect::<Vec<_>>(),
+            (0..8).collect::<Vec<_>>()
+        );
+        for x in 0..8 {
+            let cx = root.advance(&x).unwrap();
+            for y in 0..8 {
+                let cy = cx.advance(&y).unwrap();
+                let expected: Vec<_> = tuples
+                    .iter()
+                    .filter(|t| t[0] == x && t[1] == y)
+                    .map(|t| t[2])
+                    .collect();
+                assert_eq!(cy.keys().copied().collect::<Vec<_>>(), expected);
+                assert_eq!(cy.len_hint(), expected.len());
+                assert_eq!(cy.depth(), 2);
+                assert!(cy.advance(&expected[0]).unwrap().advance(&0).is_none());
+            }
+            assert!(cx.len_hint() >= cx.len());
+            assert!(cx.advance(&8).is_none());
+        }
+
+        let t2: Trie<_, 3> = Oneshot::from_iter([[1, 2, 0], [1, 2, 4], [1, 2, 5], [3, 0, 0]], &a);
+        let c = root.advance(&1).unwrap().advance(&2).unwrap();
+        let c2 = t2.open().advance(&1).unwrap().advance(&2).unwrap();
+        assert_eq!(
+            c.intersect_with([c2])
+                .map(|(k, _, _)| *k)
+                .collect::<Vec<_>>(),
+            vec![0, 5]
+        );
+    }
 }
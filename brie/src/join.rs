@@ -0,0 +1,518 @@
+//! Intersecting tries at different levels, and Generic Join on top of it.
+//!
+//! [`Cursor::intersect_with`] needs every participant to be the same kind
+//! of node, and a const number of them. A join variable, though, is bound
+//! by however many relations mention it, at whichever column it appears in
+//! each: for the triangle `R(x, y), S(y, z), T(x, z)`, `z` is level 1 of
+//! both `S` and `T`, while `y` is level 1 of `R` but level 0 of `S`.
+//! [`intersect`] takes those `(cursor, level)` participants as a slice.
+//!
+//! [`generic_join`] runs a whole query on the calling thread, while
+//! [`par_generic_join`] splits it between worker threads by the value of the
+//! first variable.
+
+use std::{
+    iter,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bumpalo::Bump;
+use itertools::Either;
+
+use crate::{on_workers, Cursor};
+
+/// How [`intersect`] finds the keys its participants have in common.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Walk the smallest node's keys, looking each up in the others.
+    Probe,
+    /// Merge the two smallest nodes with the backend's own
+    /// [`Cursor::intersect_with`], looking the survivors up in the others.
+    Merge,
+}
+
+/// Probing wins once the smallest node is this many times smaller than the
+/// next smallest; otherwise a merge touches few enough extra keys that it
+/// beats a lookup per key.
+const PROBE_RATIO: usize = 8;
+
+impl Strategy {
+    /// Picks a strategy from the [`Cursor::len_hint`] of every participant.
+    pub fn choose(lens: &[usize]) -> Self {
+        let mut smallest = [usize::MAX; 2];
+        for &len in lens {
+            if len < smallest[0] {
+                smallest = [len, smallest[0]];
+            } else if len < smallest[1] {
+                smallest[1] = len;
+            }
+        }
+
+        match smallest {
+            [_, usize::MAX] => Strategy::Probe,
+            [a, b] if a.saturating_mul(PROBE_RATIO) <= b => Strategy::Probe,
+            _ => Strategy::Merge,
+        }
+    }
+}
+
+/// Intersects the keys of every participant's node, yielding each common
+/// key with every participant's child under it, in participant order.
+///
+/// A participant is a cursor along with the level the variable lives at in
+/// that cursor's trie, which must be the cursor's depth: the relation's
+/// earlier columns have to be bound already.
+///
+/// Participants are visited smallest first, by [`Cursor::len_hint`], and the
+/// [`Strategy`] is chosen afresh for every call: the smallest one or two
+/// are walked, and the rest are probed with [`Cursor::advance`] for each
+/// key that survives.
+///
+/// Panics if there are no participants, or a cursor is at the wrong level.
+pub fn intersect<'a, C>(
+    participants: &[(C, usize)],
+) -> impl Iterator<Item = (&'a C::Value, Vec<C>)> + 'a
+where
+    C: Cursor<'a> + 'a,
+{
+    for (c, level) in participants {
+        assert_eq!(c.depth(), *level, "cursor isn't at its participant's level");
+    }
+    assert!(!participants.is_empty(), "can't intersect no participants");
+
+    let lens = participants
+        .iter()
+        .map(|(c, _)| c.len_hint())
+        .collect::<Vec<_>>();
+    let mut order = (0..participants.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| lens[i]);
+
+    let cursor = |i: usize| participants[i].0.clone();
+    let (walked, probed) = match Strategy::choose(&lens) {
+        Strategy::Probe => order.split_at(1),
+        Strategy::Merge => order.split_at(2),
+    };
+    let walked = match *walked {
+        [a, b] => Either::Left(
+            cursor(a)
+                .intersect_with([cursor(b)])
+                .map(|(k, c0, [c1])| (k, vec![c0, c1])),
+        ),
+        [a] => Either::Right(cursor(a).intersect_with([]).map(|(k, c, [])| (k, vec![c]))),
+        _ => unreachable!(),
+    };
+    let probed = probed.iter().map(|&i| cursor(i)).collect::<Vec<_>>();
+
+    walked.filter_map(move |(k, mut children)| {
+        for c in probed.iter() {
+            children.push(c.advance(k)?);
+        }
+
+        // Back from smallest first into participant order
+        let mut res = vec![None; children.len()];
+        for (&i, child) in order.iter().zip(children) {
+            res[i] = Some(child);
+        }
+        Some((k, res.into_iter().map(Option::unwrap).collect()))
+    })
+}
+
+/// Which participants bind each variable of a join, in the order the
+/// variables are bound.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    vars: Vec<Vec<(usize, usize)>>,
+}
+
+impl Plan {
+    /// Plans a join from the variables of every atom: `atoms[r][l]` is the
+    /// variable at level `l` of relation `r`. Variables are numbered from
+    /// `0` in the order they'll be bound, so the triangle query above is
+    /// `[[0, 1], [1, 2], [0, 2]]`.
+    ///
+    /// Panics if an atom's variables aren't strictly increasing, since its
+    /// trie couldn't be walked in variable order, or a variable is unused.
+    pub fn new<A: AsRef<[usize]>>(atoms: &[A]) -> Self {
+        let mut vars: Vec<Vec<(usize, usize)>> = Vec::new();
+
+        for (r, atom) in atoms.iter().enumerate() {
+            let atom = atom.as_ref();
+            assert!(
+                atom.windows(2).all(|w| w[0] < w[1]),
+                "atom {} doesn't follow the variable order",
+                r
+            );
+
+            for (l, &v) in atom.iter().enumerate() {
+                if vars.len() <= v {
+                    vars.resize_with(v + 1, Vec::new);
+                }
+                vars[v].push((r, l));
+            }
+        }
+
+        assert!(
+            vars.iter().all(|ps| !ps.is_empty()),
+            "every variable must appear in some atom"
+        );
+        Self { vars }
+    }
+
+    /// The number of variables in the join.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// The `(relation, level)` participants for variable `var`.
+    #[inline]
+    pub fn participants(&self, var: usize) -> &[(usize, usize)] {
+        &self.vars[var]
+    }
+}
+
+/// Runs Generic Join over the tries opened at `roots`, one per atom of
+/// `plan`, calling `f` with the value of every variable for each result.
+pub fn generic_join<'a, C, F>(plan: &Plan, roots: &[C], mut f: F)
+where
+    C: Cursor<'a> + 'a,
+    F: FnMut(&[&'a C::Value]),
+{
+    let mut cursors = roots.to_vec();
+    let mut binding = Vec::with_capacity(plan.len());
+    join_from(plan, 0, &mut cursors, &mut binding, &mut f);
+}
+
+/// How many chunks [`par_generic_join`] splits the first variable's keys
+/// into per worker. Workers take chunks as they finish the last one, so a
+/// few big subtrees don't leave the other workers idle.
+const CHUNKS_PER_WORKER: usize = 8;
+
+/// Runs Generic Join like [`generic_join`], but on one worker thread per
+/// arena in `arenas`.
+///
+/// The keys of the first variable are found up front, and handed out to the
+/// workers in chunks; each worker joins the subtrees under its keys on its
+/// own. The calling thread is the first worker. A worker starts with the
+/// state `init` makes from its arena, and calls `f` with that state and its
+/// arena for each of its results, so results can be kept in the arena
+/// without contending with the other workers. The states come back in the
+/// order of `arenas`, and can be merged once every worker is done, or `f`
+/// can stream results out as they're found, say down a channel.
+///
+/// Results are the same as [`generic_join`]'s, but spread between the
+/// workers in no particular order.
+///
+/// Panics if `arenas` is empty. If `f` panics on any worker, the join
+/// panics once the other workers have stopped.
+pub fn par_generic_join<'a, 'w, C, S, I, F>(
+    plan: &Plan,
+    roots: &[C],
+    arenas: &'w mut [Bump],
+    init: I,
+    f: F,
+) -> Vec<S>
+where
+    C: Cursor<'a> + Sync + 'a,
+    C::Value: Sync,
+    S: Send,
+    I: Fn(&'w Bump) -> S + Sync,
+    F: Fn(&mut S, &'w Bump, &[&'a C::Value]) + Sync,
+{
+    assert!(!arenas.is_empty(), "can't join without any workers");
+
+    if plan.is_empty() {
+        let mut arenas = arenas.iter_mut().map(|a| &*a);
+        let first = arenas.next().unwrap();
+        let mut states = vec![init(first)];
+        f(&mut states[0], first, &[]);
+        states.extend(arenas.map(init));
+        return states;
+    }
+
+    let ps = plan.participants(0);
+    let participants = ps
+        .iter()
+        .map(|&(r, l)| (roots[r].clone(), l))
+        .collect::<Vec<_>>();
+    let keys = intersect(&participants).collect::<Vec<_>>();
+
+    let chunk = (keys.len() / (arenas.len() * CHUNKS_PER_WORKER)).max(1);
+    let next = AtomicUsize::new(0);
+
+    let workers = arenas.len();
+    let (_, states) = on_workers(arenas, iter::repeat_n((), workers), |arena, ()| {
+        let mut state = init(arena);
+        let mut cursors = roots.to_vec();
+        let mut binding = Vec::with_capacity(plan.len());
+
+        loop {
+            let start = next.fetch_add(chunk, Ordering::Relaxed);
+            if start >= keys.len() {
+                break;
+            }
+
+            for (k, children) in &keys[start..(start + chunk).min(keys.len())] {
+                for (&(r, _), child) in ps.iter().zip(children) {
+                    cursors[r] = child.clone();
+                }
+                binding.push(*k);
+                join_from(plan, 1, &mut cursors, &mut binding, &mut |b| {
+                    f(&mut state, arena, b)
+                });
+                binding.pop();
+            }
+        }
+
+        state
+    });
+    states
+}
+
+fn join_from<'a, C, F>(
+    plan: &Plan,
+    var: usize,
+    cursors: &mut [C],
+    binding: &mut Vec<&'a C::Value>,
+    f: &mut F,
+) where
+    C: Cursor<'a> + 'a,
+    F: FnMut(&[&'a C::Value]),
+{
+    if var == plan.len() {
+        f(binding);
+        return;
+    }
+
+    let ps = plan.participants(var);
+    let participants = ps
+        .iter()
+        .map(|&(r, l)| (cursors[r].clone(), l))
+        .collect::<Vec<_>>();
+
+    for (k, children) in intersect(&participants) {
+        for (&(r, _), child) in ps.iter().zip(children) {
+            cursors[r] = child;
+        }
+        binding.push(k);
+        join_from(plan, var + 1, cursors, binding, f);
+        binding.pop();
+    }
+
+    // Put the cursors back where our caller left them
+    for ((r, _), (c, _)) in ps.iter().zip(participants) {
+        cursors[*r] = c;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use bumpalo::Bump;
+
+    use crate::{
+        hash,
+        sorted::{self, flat, vec::BumpVec},
+        Cursor, Oneshot,
+    };
+
+    use super::{generic_join, intersect, par_generic_join, Plan, Strategy};
+
+    /// A cursor which counts the calls that walk its keys.
+    #[derive(Clone)]
+    struct Counted<'a, C> {
+        inner: C,
+        walks: &'a Cell<usize>,
+    }
+
+    impl<'a, C> Counted<'a, C> {
+        fn walk(&self) {
+            self.walks.set(self.walks.get() + 1);
+        }
+    }
+
+    impl<'a, C: Cursor<'a> + 'a> Cursor<'a> for Counted<'a, C> {
+        type Value = C::Value;
+        type Keys = C::Keys;
+        type Intersect<const M: usize> =
+            Box<dyn Iterator<Item = (&'a C::Value, Self, [Self; M])> + 'a>;
+
+        fn depth(&self) -> usize {
+            self.inner.depth()
+        }
+
+        fn len(&self) -> usize {
+            self.walk();
+            self.inner.len()
+        }
+
+        fn len_hint(&self) -> usize {
+            self.inner.len_hint()
+        }
+
+        fn keys(&self) -> Self::Keys {
+            self.walk();
+            self.inner.keys()
+        }
+
+        fn advance(&self, v: &C::Value) -> Option<Self> {
+            Some(Self {
+                inner: self.inner.advance(v)?,
+                walks: self.walks,
+            })
+        }
+
+        fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+            self.walk();
+            others.iter().for_each(Self::walk);
+
+            let walks = self.walks;
+            let other_walks = others.each_ref().map(|c| c.walks);
+            Box::new(self.inner.intersect_with(others.map(|c| c.inner)).map(
+                move |(k, inner, children)| {
+                    let mut walks_of = other_walks.iter();
+                    let children = children.map(|inner| Counted {
+                        inner,
+                        walks: walks_of.next().unwrap(),
+                    });
+                    (k, Counted { inner, walks }, children)
+                },
+            ))
+        }
+    }
+
+    #[test]
+    fn levels() {
+        let a = Bump::new();
+        let r: sorted::Trie<i32> = Oneshot::<2>::from_iter([[1, 2], [1, 3], [2, 3]], &a);
+        let s: sorted::Trie<i32> = Oneshot::<2>::from_iter([[3, 0], [4, 0], [2, 1]], &a);
+        let t: sorted::Trie<i32> = Oneshot::<2>::from_iter([[3, 7], [9, 9]], &a);
+
+        // y is level 1 of r (under x = 1), and level 0 of s and t
+        let r1 = Oneshot::<2>::open(&r).advance(&1).unwrap();
+        let ps = [
+            (r1, 1),
+            (Oneshot::<2>::open(&s), 0),
+            (Oneshot::<2>::open(&t), 0),
+        ];
+
+        let res = intersect(&ps).collect::<Vec<_>>();
+        assert_eq!(res.len(), 1);
+        let (k, children) = &res[0];
+        assert_eq!(**k, 3);
+        assert_eq!(
+            children.iter().map(|c| c.depth()).collect::<Vec<_>>(),
+            vec![2, 1, 1]
+        );
+        assert_eq!(children[2].keys().copied().collect::<Vec<_>>(), vec![7]);
+    }
+
+    #[test]
+    fn adaptive() {
+        assert_eq!(Strategy::choose(&[1000, 3, 500]), Strategy::Probe);
+        assert_eq!(Strategy::choose(&[10, 12, 3000]), Strategy::Merge);
+        assert_eq!(Strategy::choose(&[7]), Strategy::Probe);
+
+        let a = Bump::new();
+        let big: sorted::Trie<i32> = Oneshot::<1>::from_iter((0..1000).map(|x| [x]), &a);
+        let evens: sorted::Trie<i32> =
+            Oneshot::<1>::from_iter((0..1000).step_by(2).map(|x| [x]), &a);
+        let few: sorted::Trie<i32> = Oneshot::<1>::from_iter([[4], [5], [998]], &a);
+
+        // Whichever way round, children come back in participant order
+        for ps in [[&big, &evens, &few], [&few, &big, &evens]] {
+            let ps = ps.map(|t| (Oneshot::<1>::open(t), 0));
+            let res = intersect(&ps).collect::<Vec<_>>();
+            assert_eq!(
+                res.iter().map(|(k, _)| **k).collect::<Vec<_>>(),
+                vec![4, 998]
+            );
+            for (k, children) in res {
+                for (child, (c, _)) in children.iter().zip(ps.iter()) {
+                    assert!(std::ptr::eq(child.node(), c.advance(k).unwrap().node()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sized_without_walking() {
+        let (small_walks, big_walks) = (Cell::new(0), Cell::new(0));
+        let a = Bump::new();
+
+        // Flat and hashed nodes count their keys by walking them, so picking
+        // which side to walk mustn't ask for the big side's count
+        let small: flat::Trie<i32, 1, flat::Read> = Oneshot::from_iter([[3], [500], [20_000]], &a);
+        let big: flat::Trie<i32, 1, flat::Read> = Oneshot::from_iter((0..10_000).map(|x| [x]), &a);
+        let ps = [(big.open(), &big_walks), (small.open(), &small_walks)]
+            .map(|(inner, walks)| (Counted { inner, walks }, 0));
+        let keys = intersect(&ps).map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec![3, 500]);
+        assert_eq!((small_walks.get(), big_walks.get()), (1, 0));
+
+        let small: hash::Trie<i32, 2> = Oneshot::from_iter([[1, 3], [1, 500], [2, 4]], &a);
+        let big: hash::Trie<i32, 2> = Oneshot::from_iter((0..10_000).map(|x| [x % 2, x]), &a);
+        big_walks.set(0);
+        let ps = [
+            (big.open().advance(&1).unwrap(), &big_walks),
+            (small.open().advance(&1).unwrap(), &small_walks),
+        ]
+        .map(|(inner, walks)| (Counted { inner, walks }, 1));
+        let keys = intersect(&ps).map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec![3]);
+        assert_eq!(big_walks.get(), 0);
+    }
+
+    #[test]
+    fn triangle() {
+        let a = Bump::new();
+        let edges = [[0, 1], [1, 2], [0, 2], [2, 3], [1, 3], [3, 4]];
+        let e: hash::Trie<i32, 2> = Oneshot::from_iter(edges, &a);
+
+        let plan = Plan::new(&[[0, 1], [1, 2], [0, 2]]);
+        let roots = [e.open(); 3];
+
+        let mut res = Vec::new();
+        generic_join(&plan, &roots, |b| res.push([*b[0], *b[1], *b[2]]));
+        res.sort_unstable();
+        assert_eq!(res, vec![[0, 1, 2], [1, 2, 3]]);
+    }
+
+    #[test]
+    fn parallel() {
+        let a = Bump::new();
+        let edges = (0..60)
+            .flat_map(|x| (x + 1..60).map(move |y| [x, y]))
+            .filter(|[x, y]| (x * y) % 7 == 1 || (x + y) % 5 == 0);
+        let e: sorted::Trie<i32> = Oneshot::<2>::from_iter(edges, &a);
+
+        let plan = Plan::new(&[[0, 1], [1, 2], [0, 2]]);
+        let roots = [Oneshot::<2>::open(&e); 3];
+
+        let mut expected = Vec::new();
+        generic_join(&plan, &roots, |b| expected.push([*b[0], *b[1], *b[2]]));
+        expected.sort_unstable();
+        assert_eq!(expected.len(), 844);
+
+        // Each worker collects into a vec in its own arena
+        let mut arenas: Vec<Bump> = (0..4).map(|_| Bump::new()).collect();
+        let outputs = par_generic_join(
+            &plan,
+            &roots,
+            &mut arenas,
+            |_| BumpVec::new(),
+            |out, bump, b| out.push([*b[0], *b[1], *b[2]], bump),
+        );
+        assert_eq!(outputs.len(), 4);
+
+        let mut res = outputs.into_iter().flatten().collect::<Vec<_>>();
+        res.sort_unstable();
+        assert_eq!(res, expected);
+    }
+}
@@ -0,0 +1,66 @@
+//! Leaf semantics for tries which store whole tuples at their leaves.
+//!
+//! Inserting the same tuple twice either collapses into one leaf ([`Set`])
+//! or bumps a counter stored on the leaf ([`Bag`]). Either way duplicates
+//! are resolved at insertion time, so a leaf is always exactly one tuple.
+
+use std::fmt::Debug;
+
+/// Decides what happens when a tuple is inserted into a leaf which
+/// already holds it.
+pub trait Semantics: 'static {
+    /// The multiplicity stored alongside each tuple.
+    type Count: Copy + Debug;
+
+    /// The multiplicity of a freshly inserted tuple.
+    fn one() -> Self::Count;
+
+    /// Records another insertion of the same tuple.
+    fn bump(count: &mut Self::Count);
+
+    /// How many times the tuple was inserted, as seen by queries.
+    fn get(count: &Self::Count) -> usize;
+}
+
+/// Set semantics: duplicates are dropped on insertion.
+/// Leaves don't pay for a counter.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Set;
+
+impl Semantics for Set {
+    type Count = ();
+
+    #[inline]
+    fn one() -> Self::Count {}
+
+    #[inline]
+    fn bump(_count: &mut Self::Count) {}
+
+    #[inline]
+    fn get(_count: &Self::Count) -> usize {
+        1
+    }
+}
+
+/// Bag semantics: every leaf counts how many times its tuple was inserted.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bag;
+
+impl Semantics for Bag {
+    type Count = usize;
+
+    #[inline]
+    fn one() -> Self::Count {
+        1
+    }
+
+    #[inline]
+    fn bump(count: &mut Self::Count) {
+        *count += 1;
+    }
+
+    #[inline]
+    fn get(count: &Self::Count) -> usize {
+        *count
+    }
+}
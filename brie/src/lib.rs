@@ -1,17 +1,122 @@
-#![feature(allocator_api)]
-#![feature(core_intrinsics)]
-#![feature(alloc_layout_extra)]
-#![feature(slice_ptr_get)]
-#![feature(generic_associated_types)]
-#![feature(int_log)]
-#![feature(type_alias_impl_trait)]
+#![cfg_attr(
+    feature = "nightly",
+    feature(allocator_api, slice_ptr_get, impl_trait_in_assoc_type)
+)]
+
+use std::{hash::BuildHasher, panic, thread};
 
 use bumpalo::Bump;
 
+use crate::sorted::vec::CollectionAllocError;
+
+/// The type of an iterator returned through an associated type, like
+/// [`Cursor::Keys`], which lives for `$lt`. With the `nightly` feature this
+/// is an unboxed `impl Iterator`; stable Rust can't name those, so it's
+/// boxed instead. Values of the type are made with
+/// [`IntoIterType::into_iter_type`].
+#[cfg(feature = "nightly")]
+macro_rules! iter_type {
+    ($lt:lifetime, $item:ty) => { impl Iterator<Item = $item> + $lt };
+}
+
+#[cfg(not(feature = "nightly"))]
+macro_rules! iter_type {
+    ($lt:lifetime, $item:ty) => { ::std::boxed::Box<dyn Iterator<Item = $item> + $lt> };
+}
+
+/// Turns an iterator into an `iter_type!`: a no-op on nightly, and a box on
+/// stable.
+pub(crate) trait IntoIterType: Iterator + Sized {
+    #[cfg(feature = "nightly")]
+    #[inline]
+    fn into_iter_type(self) -> Self {
+        self
+    }
+
+    #[cfg(not(feature = "nightly"))]
+    #[inline]
+    fn into_iter_type<'a>(self) -> Box<dyn Iterator<Item = Self::Item> + 'a>
+    where
+        Self: 'a,
+    {
+        Box::new(self)
+    }
+}
+
+impl<I: Iterator> IntoIterType for I {}
+
+/// Runs `work` on one worker per arena in `arenas`, handing each its arena
+/// and the next of `inputs`. The first worker is the calling thread, and the
+/// rest are scoped threads; workers past the end of `inputs` don't start.
+///
+/// Returns the first arena, which the calling thread can go on allocating
+/// from, and the workers' results in the order of `arenas`. A panic in a
+/// worker is passed on once all of them have stopped.
+pub(crate) fn on_workers<'w, I, R, F>(
+    arenas: &'w mut [Bump],
+    inputs: impl IntoIterator<Item = I>,
+    work: F,
+) -> (&'w Bump, Vec<R>)
+where
+    I: Send,
+    R: Send,
+    F: Fn(&'w Bump, I) -> R + Sync,
+{
+    let (first, rest) = arenas
+        .split_first_mut()
+        .expect("can't run without any workers");
+    let first: &'w Bump = first;
+    let mut inputs = inputs.into_iter();
+    let mine = match inputs.next() {
+        Some(input) => input,
+        None => return (first, Vec::new()),
+    };
+
+    let work = &work;
+    let res = thread::scope(|scope| {
+        let others = rest
+            .iter_mut()
+            .zip(inputs)
+            .map(|(arena, input)| scope.spawn(move || work(arena, input)))
+            .collect::<Vec<_>>();
+
+        let mut res = vec![work(first, mine)];
+        res.extend(
+            others
+                .into_iter()
+                .map(|w| w.join().unwrap_or_else(|e| panic::resume_unwind(e))),
+        );
+        res
+    });
+    (first, res)
+}
+
+/// Builds an array by calling `f` with each index in turn, like
+/// [`std::array::from_fn`], but gives up on the first `None`. This stands in
+/// for the unstable `std::array::try_from_fn`.
+pub(crate) fn try_from_fn<T, const M: usize>(
+    mut f: impl FnMut(usize) -> Option<T>,
+) -> Option<[T; M]> {
+    let mut res: [Option<T>; M] = std::array::from_fn(|_| None);
+    for (i, x) in res.iter_mut().enumerate() {
+        *x = Some(f(i)?);
+    }
+    Some(res.map(Option::unwrap))
+}
+
+pub mod arena;
+pub mod dict;
 pub mod hash;
+pub mod join;
+pub mod leaf;
+pub mod prehash;
 pub mod simple_hash;
 pub mod skip_list;
 pub mod sorted;
+pub mod stats;
+pub mod store;
+#[cfg(test)]
+mod testing;
 pub mod vanilla;
 
 pub trait Oneshot<'bump, const N: usize>
@@ -23,16 +128,159 @@ where
     type KeyIter<const M: usize>: Iterator<Item = Self::IVal> + 'bump
     where
         Self: 'bump;
+    type Cursor<'a>: Cursor<'a, Value = Self::Value>
+    where
+        Self: 'a;
 
     fn from_iter<I: IntoIterator<Item = [Self::Value; N]>>(iter: I, bump: &'bump Bump) -> Self;
-    fn advance(&'bump self, v: &Self::Value) -> Option<&'bump Self>;
-    fn intersect<'a, const M: usize>(
-        &'bump self,
-        others: [&'bump Self; M],
-    ) -> Self::KeyIter<M>;
+    /// A cursor at the root of the trie.
+    fn open(&self) -> Self::Cursor<'_>;
+    /// Intersects the keys of the roots of `self` and `others`. Use
+    /// [`Cursor::intersect_with`] on [`Oneshot::open`]ed cursors to also
+    /// get the children under each key.
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M>;
     // fn materialize(&self, query: [T; M]) -> impl Iterator<Item = [T; M + 1]>;
 }
 
+/// The runtime-arity counterpart of [`Oneshot`], for tries whose number of
+/// columns isn't known until they're built. Tuples are slices, and every
+/// tuple must have exactly `arity` values.
+pub trait DynOneshot<'bump>
+where
+    Self: Sized,
+{
+    type Value;
+    type IVal;
+    type KeyIter<const M: usize>: Iterator<Item = Self::IVal> + 'bump
+    where
+        Self: 'bump;
+    type Cursor<'a>: Cursor<'a, Value = Self::Value>
+    where
+        Self: 'a;
+
+    fn from_iter<I, R>(arity: usize, iter: I, bump: &'bump Bump) -> Self
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[Self::Value]>;
+    fn arity(&self) -> usize;
+    /// A cursor at the root of the trie.
+    fn open(&self) -> Self::Cursor<'_>;
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M>;
+}
+
+/// A position in a trie: some node, `depth` levels below the root.
+///
+/// Cursors are how tries are navigated, whatever their layout. Pointer-based
+/// tries use a [`NodeCursor`], while flat tries use an index or a range into
+/// their arrays; either way cursors are cheap to copy around.
+pub trait Cursor<'a>: Clone {
+    type Value: 'a;
+    type Keys: Iterator<Item = &'a Self::Value> + 'a;
+    type Intersect<const M: usize>: Iterator<Item = (&'a Self::Value, Self, [Self; M])> + 'a;
+
+    /// How many values have been advanced past since the root. Cursors at
+    /// the trie's arity are at a leaf, which has no keys.
+    fn depth(&self) -> usize;
+
+    /// The number of keys at this node. This is a field read for most
+    /// backends, but flat and hashed layouts count their keys by walking
+    /// them.
+    fn len(&self) -> usize;
+
+    /// An upper bound on [`Cursor::len`] which never walks the keys, for
+    /// sizing up nodes before deciding which to walk. Backends whose `len`
+    /// is already a field read just return it.
+    #[inline]
+    fn len_hint(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The keys of the children of this node.
+    fn keys(&self) -> Self::Keys;
+
+    /// Moves to the child under `v`, if there is one.
+    fn advance(&self, v: &Self::Value) -> Option<Self>;
+
+    /// The keys of this node's children which are also keys of every other
+    /// node's children, along with the children under each key: this
+    /// node's, and then each of the others' in order. Descending into them
+    /// doesn't need another lookup.
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M>;
+}
+
+/// A [`Cursor`] for tries whose nodes are themselves tries.
+pub struct NodeCursor<'a, T> {
+    pub(crate) node: &'a T,
+    pub(crate) depth: usize,
+}
+
+impl<'a, T> NodeCursor<'a, T> {
+    #[inline]
+    pub fn new(root: &'a T) -> Self {
+        Self {
+            node: root,
+            depth: 0,
+        }
+    }
+
+    /// The node this cursor points at.
+    #[inline]
+    pub fn node(&self) -> &'a T {
+        self.node
+    }
+
+    #[inline]
+    pub(crate) fn child(&self, node: &'a T) -> Self {
+        Self {
+            node,
+            depth: self.depth + 1,
+        }
+    }
+}
+
+impl<'a, T> Clone for NodeCursor<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for NodeCursor<'a, T> {}
+
+/// A [`Oneshot`] trie which hashes its keys, and so can be built with any
+/// hasher. [`Oneshot::from_iter`] uses `Self::BuildHasher::default()`.
+///
+/// Tries can only be intersected with tries built by an identical hasher.
+pub trait HashOneshot<'bump, const N: usize>: Oneshot<'bump, N> {
+    type BuildHasher: BuildHasher + Default;
+
+    fn from_iter_with_hasher<I: IntoIterator<Item = [Self::Value; N]>>(
+        iter: I,
+        hasher: Self::BuildHasher,
+        bump: &'bump Bump,
+    ) -> Self;
+}
+
+/// A [`Oneshot`] trie which can be built without panicking when its arena
+/// runs out of room, such as an [`arena::Arena`] with a budget.
+///
+/// It's implemented by [`sorted::Trie`], [`sorted::flat::Trie`],
+/// [`skip_list::SkipList`], [`simple_hash::Trie`], [`prehash::Trie`] and
+/// [`hash::Trie`], and the dynamic-arity [`sorted::DynTrie`] and
+/// [`hash::DynTrie`] have a `try_from_iter` of their own. [`sorted::soa::Trie`] and the `vanilla`
+/// tries still allocate through panicking paths.
+pub trait TryOneshot<'bump, const N: usize>: Oneshot<'bump, N> {
+    /// The fallible counterpart of [`Oneshot::from_iter`].
+    fn try_from_iter<I: IntoIterator<Item = [Self::Value; N]>>(
+        iter: I,
+        bump: &'bump Bump,
+    ) -> Result<Self, CollectionAllocError>;
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -40,4 +288,17 @@ mod tests {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn try_from_fn() {
+        let squares: Option<[String; 3]> = super::try_from_fn(|i| Some((i * i).to_string()));
+        assert_eq!(squares, Some(["0".into(), "1".into(), "4".into()]));
+
+        let mut calls = 0;
+        let none: Option<[String; 3]> = super::try_from_fn(|i| {
+            calls += 1;
+            (i < 1).then(String::new)
+        });
+        assert_eq!((none, calls), (None, 2));
+    }
 }
@@ -0,0 +1,518 @@
+//! Pre-hashed keys, for integer IDs which are (nearly) their own hash.
+//!
+//! Dense integer IDs (e.g. e-class IDs) don't need a real hash function: a
+//! single multiplication by an odd constant spreads them across the whole
+//! `u64` range, and is cheap enough to redo at every level of a trie. Build
+//! any of the hash tries with [`BuildPreHasher`] to hash keys this way.
+//!
+//! Multiplying by an odd constant is a bijection on `u64`, so two
+//! [`PreHashed`] keys have the same hash exactly when they're equal. There's
+//! then no point storing the hash next to the key, or the key next to the
+//! hash: [`Trie`] stores just the key in each bucket, and recomputes its
+//! hash whenever it needs it.
+
+use std::{
+    hash::{BuildHasherDefault, Hash, Hasher},
+    mem,
+};
+
+use bumpalo::Bump;
+
+use crate::{
+    sorted::vec::{BumpVec, CollectionAllocError},
+    stats::{Stats, TrieStats},
+    store::Storable,
+    IntoIterType, Oneshot, TryOneshot,
+};
+
+/// Fibonacci hashing constant, `2^64 / φ`. Odd, so multiplying by it is
+/// invertible, and it mixes low bits into the high bits that tries use to
+/// pick buckets.
+const K: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Keys which can be hashed by a single multiplication.
+///
+/// Implementors' [`Hash`] impls must write exactly one integer, so that
+/// hashing with [`PreHasher`] gives the same result as
+/// [`PreHashed::pre_hash`].
+pub trait PreHashed: Hash + Ord + Copy {
+    fn pre_hash(self) -> u64;
+}
+
+macro_rules! impl_pre_hashed {
+    ($($t:ty),*) => {
+        $(
+            impl PreHashed for $t {
+                #[inline]
+                fn pre_hash(self) -> u64 {
+                    (self as u64).wrapping_mul(K)
+                }
+            }
+        )*
+    };
+}
+
+impl_pre_hashed!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// A [`Hasher`] for [`PreHashed`] keys.
+///
+/// Every integer written is folded into the state and multiplied through,
+/// so composite keys (like `hash::Trie`'s `(parent, key)` pairs) still hash
+/// reasonably, but it's only fast when keys are single integers.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PreHasher(u64);
+
+impl PreHasher {
+    #[inline]
+    fn mix(&mut self, x: u64) {
+        self.0 = (self.0.rotate_left(26) ^ x).wrapping_mul(K);
+    }
+}
+
+impl Hasher for PreHasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut buf = [0; 8];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            self.mix(u64::from_le_bytes(buf));
+        }
+    }
+
+    #[inline]
+    fn write_u8(&mut self, i: u8) {
+        self.mix(i as u64);
+    }
+
+    #[inline]
+    fn write_u16(&mut self, i: u16) {
+        self.mix(i as u64);
+    }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32) {
+        self.mix(i as u64);
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64) {
+        self.mix(i);
+    }
+
+    #[inline]
+    fn write_usize(&mut self, i: usize) {
+        self.mix(i as u64);
+    }
+
+    #[inline]
+    fn write_i8(&mut self, i: i8) {
+        self.mix(i as u64);
+    }
+
+    #[inline]
+    fn write_i16(&mut self, i: i16) {
+        self.mix(i as u64);
+    }
+
+    #[inline]
+    fn write_i32(&mut self, i: i32) {
+        self.mix(i as u64);
+    }
+
+    #[inline]
+    fn write_i64(&mut self, i: i64) {
+        self.mix(i as u64);
+    }
+
+    #[inline]
+    fn write_isize(&mut self, i: isize) {
+        self.mix(i as u64);
+    }
+}
+
+/// Plug into any hash trie's `B` parameter to hash keys with [`PreHasher`].
+pub type BuildPreHasher = BuildHasherDefault<PreHasher>;
+
+/// A nested hash trie over [`PreHashed`] keys, whose buckets hold the keys
+/// themselves rather than their hashes.
+///
+/// Every per-node map lives in `slots`, one after another in the order a
+/// depth-first walk meets them, and is sized for exactly the keys it holds.
+/// A bucket is the key plus its child's index in `maps`, so for `u32` keys
+/// it takes 8 bytes, half a [`crate::simple_hash::Entry`]. Keys are matched
+/// directly, without hashing or following them down to a leaf.
+///
+/// Tuples are kept with set semantics.
+pub struct Trie<'b, K, const N: usize> {
+    slots: BumpVec<'b, Slot<K>>,
+    /// Every node's map; the root's comes first.
+    maps: BumpVec<'b, Map>,
+}
+
+/// A bucket in one of the trie's maps.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Slot<K> {
+    key: K,
+    /// What's under `key`: [`EMPTY`] if the bucket is unoccupied, [`LEAF`]
+    /// at the last level, and otherwise one more than the child's index in
+    /// `maps`.
+    child: u32,
+}
+
+const EMPTY: u32 = 0;
+const LEAF: u32 = u32::MAX;
+
+/// Where a node's map is in `slots`: `2 ^ bits` buckets from `offset`,
+/// holding `len` keys.
+#[derive(Copy, Clone, Debug)]
+struct Map {
+    offset: usize,
+    bits: u32,
+    len: usize,
+}
+
+impl<'b, K: PreHashed, const N: usize> Trie<'b, K, N> {
+    /// Points at the root's map, if the trie has any tuples.
+    #[inline]
+    fn root(&self) -> u32 {
+        if self.maps.is_empty() {
+            EMPTY
+        } else {
+            1
+        }
+    }
+
+    /// The buckets of the node `child` points at, or none at all if it's a
+    /// leaf.
+    #[inline]
+    fn map_of(&self, child: u32) -> &[Slot<K>] {
+        match child {
+            EMPTY | LEAF => &[],
+            ix => {
+                let map = &self.maps[ix as usize - 1];
+                &self.slots[map.offset..map.offset + (1 << map.bits)]
+            }
+        }
+    }
+
+    /// The bucket of `map` holding `key`, if there is one.
+    #[inline]
+    fn find(map: &[Slot<K>], key: K) -> Option<&Slot<K>> {
+        let mask = map.len().checked_sub(1)?;
+        let start = Self::bucket_of(key, map.len().trailing_zeros());
+
+        (0..map.len())
+            .map(|i| &map[(start + i) & mask])
+            .take_while(|s| s.child != EMPTY)
+            .find(|s| s.key == key)
+    }
+
+    /// Buckets are picked using the top `bits` bits of the hash.
+    #[inline]
+    fn bucket_of(key: K, bits: u32) -> usize {
+        key.pre_hash().checked_shr(u64::BITS - bits).unwrap_or(0) as usize
+    }
+
+    fn calc_bits(len: usize) -> u32 {
+        (len as f64 * 1.25).log2().ceil() as u32
+    }
+}
+
+impl<'b, K: PreHashed + Default, const N: usize> Trie<'b, K, N> {
+    fn try_build<I: IntoIterator<Item = [K; N]>>(
+        iter: I,
+        bump: &'b Bump,
+    ) -> Result<Self, CollectionAllocError> {
+        let mut tuples = iter.into_iter().collect::<Vec<_>>();
+        tuples.sort_unstable();
+        tuples.dedup();
+
+        // The first level at which each tuple parts from the one before it.
+        // Every key from there on is new, and so is every node below it.
+        let first = |i: usize| match i.checked_sub(1) {
+            None => 0,
+            Some(p) => (0..N).position(|l| tuples[p][l] != tuples[i][l]).unwrap(),
+        };
+
+        // Count every node's keys, numbering the nodes as they're met
+        let mut fanouts = Vec::new();
+        let mut open = [0; N];
+        for i in 0..tuples.len() {
+            if i == 0 {
+                fanouts.push(0);
+            }
+            for l in first(i)..N {
+                if l + 1 < N {
+                    open[l + 1] = fanouts.len();
+                    fanouts.push(0);
+                }
+                fanouts[open[l]] += 1;
+            }
+        }
+
+        assert!(fanouts.len() < LEAF as usize, "too many nodes");
+        let mut maps = BumpVec::try_with_capacity_in(fanouts.len(), bump)?;
+        let mut offset = 0;
+        for len in fanouts {
+            let bits = Self::calc_bits(len);
+            maps.push(Map { offset, bits, len }, bump);
+            offset += 1 << bits;
+        }
+
+        let mut slots = BumpVec::try_with_capacity_in(offset, bump)?;
+        slots.resize(offset, Slot::default(), bump);
+
+        // Then insert every key, numbering the nodes in the same order
+        let mut next = 1;
+        for (i, tup) in tuples.iter().enumerate() {
+            for l in first(i)..N {
+                let key = tup[l];
+                let child = if l + 1 < N {
+                    open[l + 1] = next;
+                    next += 1;
+                    next as u32
+                } else {
+                    LEAF
+                };
+
+                let map = &maps[open[l]];
+                let mask = (1 << map.bits) - 1;
+                let start = Self::bucket_of(key, map.bits);
+                let region = &mut slots[map.offset..map.offset + (1 << map.bits)];
+                // Keys are sorted and deduplicated, so this one is new to its
+                // map, and the map was sized to fit it
+                let ix = (0..region.len())
+                    .map(|i| (start + i) & mask)
+                    .find(|&ix| region[ix].child == EMPTY)
+                    .unwrap();
+                region[ix] = Slot { key, child };
+            }
+        }
+
+        Ok(Self { slots, maps })
+    }
+}
+
+impl<'b, K: PreHashed + Default, const N: usize> TryOneshot<'b, N> for Trie<'b, K, N> {
+    fn try_from_iter<I: IntoIterator<Item = [K; N]>>(
+        iter: I,
+        bump: &'b Bump,
+    ) -> Result<Self, CollectionAllocError> {
+        Self::try_build(iter, bump)
+    }
+}
+
+/// A [`crate::Cursor`] into a [`Trie`]: the node `child` points at, in the
+/// same encoding as [`Slot`]s.
+pub struct Cursor<'a, 'b, K, const N: usize> {
+    trie: &'a Trie<'b, K, N>,
+    child: u32,
+    depth: usize,
+}
+
+impl<'a, 'b, K: PreHashed, const N: usize> Cursor<'a, 'b, K, N> {
+    #[inline]
+    fn map(&self) -> &'a [Slot<K>] {
+        self.trie.map_of(self.child)
+    }
+
+    #[inline]
+    fn child(&self, slot: &Slot<K>) -> Self {
+        Self {
+            child: slot.child,
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+}
+
+impl<'a, 'b, K, const N: usize> Clone for Cursor<'a, 'b, K, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, 'b, K, const N: usize> Copy for Cursor<'a, 'b, K, N> {}
+
+impl<'a, 'b: 'a, K: PreHashed + 'b, const N: usize> crate::Cursor<'a> for Cursor<'a, 'b, K, N> {
+    type Value = K;
+    type Keys = iter_type!('a, &'a K);
+    type Intersect<const M: usize> = iter_type!('a, (&'a K, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        match self.child {
+            EMPTY | LEAF => 0,
+            ix => self.trie.maps[ix as usize - 1].len,
+        }
+    }
+
+    fn keys(&self) -> Self::Keys {
+        self.map()
+            .iter()
+            .filter(|s| s.child != EMPTY)
+            .map(|s| &s.key)
+            .into_iter_type()
+    }
+
+    fn advance(&self, v: &K) -> Option<Self> {
+        Trie::<K, N>::find(self.map(), *v).map(|s| self.child(s))
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        let this = *self;
+        this.map()
+            .iter()
+            .filter(|s| s.child != EMPTY)
+            .filter_map(move |s| {
+                let children = crate::try_from_fn(|i| others[i].advance(&s.key))?;
+                Some((&s.key, this.child(s), children))
+            })
+            .into_iter_type()
+    }
+}
+
+impl<'b, K: PreHashed + Default + 'b, const N: usize> Oneshot<'b, N> for Trie<'b, K, N> {
+    type Value = K;
+    type IVal = &'b K;
+    type KeyIter<const M: usize>
+        = iter_type!('b, &'b K)
+    where
+        Self: 'b;
+    type Cursor<'a>
+        = Cursor<'a, 'b, K, N>
+    where
+        Self: 'a;
+
+    fn from_iter<I: IntoIterator<Item = [K; N]>>(iter: I, bump: &'b Bump) -> Self {
+        Self::try_build(iter, bump).unwrap_or_else(|e| e.raise())
+    }
+
+    fn open(&self) -> Self::Cursor<'_> {
+        Cursor {
+            trie: self,
+            child: self.root(),
+            depth: 0,
+        }
+    }
+
+    fn intersect<const M: usize>(&'b self, others: [&'b Self; M]) -> Self::KeyIter<M> {
+        self.map_of(self.root())
+            .iter()
+            .filter(|s| s.child != EMPTY)
+            .filter(move |s| {
+                others
+                    .iter()
+                    .all(|o| Self::find(o.map_of(o.root()), s.key).is_some())
+            })
+            .map(|s| &s.key)
+            .into_iter_type()
+    }
+}
+
+impl<K, const N: usize> Storable for Trie<'static, K, N> {
+    type Trie<'bump> = Trie<'bump, K, N>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'b, K: PreHashed + Default + 'b, const N: usize> Stats for Trie<'b, K, N> {
+    fn stats(&self) -> TrieStats {
+        let mut stats = TrieStats::walk(self.open(), |c| (c.map().len(), 0));
+        stats.bytes = self.slots.capacity() * mem::size_of::<Slot<K>>()
+            + self.maps.capacity() * mem::size_of::<Map>();
+        stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::hash::BuildHasher;
+
+    use bumpalo::Bump;
+
+    use crate::{leaf::Set, simple_hash, stats::Stats, Cursor, Oneshot};
+
+    use super::{BuildPreHasher, PreHashed, Slot, Trie};
+
+    #[test]
+    fn matches_pre_hash() {
+        let b = BuildPreHasher::default();
+        for x in [0u32, 1, 2, 1000, u32::MAX] {
+            assert_eq!(b.hash_one(x), x.pre_hash());
+        }
+        for x in [0i32, -1, 7] {
+            assert_eq!(b.hash_one(x), x.pre_hash());
+        }
+    }
+
+    #[test]
+    fn prehashed_trie() {
+        type T<'b> = simple_hash::Trie<'b, u32, 2, Set, BuildPreHasher>;
+        let a = Bump::new();
+        let t1 = T::from_iter((0..100).map(|x| [x, x % 7]), &a);
+        let t2 = T::from_iter((0..100).step_by(5).map(|x| [x, 0]), &a);
+
+        assert_eq!(t1.leaves().len(), 100);
+        assert_eq!(t1.intersect([&t2]).count(), 20);
+    }
+
+    #[test]
+    fn keys_in_buckets() {
+        let a = Bump::new();
+        let tuples = (0..100u32).flat_map(|x| (0..x % 4).map(move |y| [x % 10, y, x]));
+        let t: Trie<u32, 3> = Oneshot::from_iter(tuples.clone().chain(tuples.clone()), &a);
+        let h: simple_hash::Trie<u32, 3> = Oneshot::from_iter(tuples, &a);
+
+        // The same tuples as any other trie, found by key
+        let (root, hroot) = (t.open(), h.open());
+        let mut keys: Vec<_> = root.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
+        for x in 0..10 {
+            let (c, hc) = (root.advance(&x).unwrap(), hroot.advance(&x).unwrap());
+            assert_eq!(c.len(), hc.len());
+            for y in c.keys() {
+                assert_eq!(c.advance(y).unwrap().len(), hc.advance(y).unwrap().len());
+            }
+            assert!(c.advance(&7).is_none());
+        }
+        assert!(root.advance(&10).is_none());
+
+        // Every map fits its keys, in 8 byte buckets
+        assert_eq!(std::mem::size_of::<Slot<u32>>(), 8);
+        let (s, hs) = (t.stats(), h.stats());
+        assert_eq!(s.tuples(), hs.tuples());
+        assert!(s.levels.iter().all(|l| l.load_factor().unwrap() <= 0.8));
+        assert!(s.bytes < hs.bytes);
+    }
+
+    #[test]
+    fn intersect() {
+        let a = Bump::new();
+        let t1: Trie<i32, 1> = Oneshot::from_iter((0..1000).map(|x| [x]), &a);
+        let t2: Trie<i32, 1> = Oneshot::from_iter((0..1000).step_by(2).map(|x| [x]), &a);
+        let t3: Trie<i32, 1> = Oneshot::from_iter((-30..1000).step_by(3).map(|x| [x]), &a);
+        let mut keys: Vec<_> = t1.intersect([&t2, &t3]).copied().collect();
+        keys.sort();
+        assert_eq!(keys, (0..1000).step_by(6).collect::<Vec<_>>());
+
+        let c = t1.open().intersect_with([t2.open(), t3.open()]);
+        assert_eq!(c.count(), keys.len());
+
+        let empty: Trie<i32, 1> = Oneshot::from_iter([], &a);
+        assert_eq!(empty.open().len(), 0);
+        assert_eq!(t1.intersect([&empty]).count(), 0);
+    }
+}
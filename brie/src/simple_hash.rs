@@ -1,147 +1,243 @@
 // TODO: data-oriented opts
-//       reduce size of everything wayyyyy down (entries should just be u64 and ptr)
-//       pack data into array (partition according to paper)
 //       building optimizations from paper (singleton, lazy)
 
-use bumpalo::{boxed::Box, Bump};
 use hyperloglogplus::{HyperLogLog, HyperLogLogPF};
 
-use crate::{sorted::vec::BumpVec, Oneshot};
+use bumpalo::Bump;
+
+use crate::{
+    leaf::{Semantics, Set},
+    on_workers,
+    sorted::vec::{BumpVec, CollectionAllocError},
+    stats::{Stats, TrieStats},
+    store::Storable,
+    HashOneshot, IntoIterType, Oneshot, TryOneshot,
+};
 
 use std::{
-    hash::{Hash, Hasher},
-    mem::MaybeUninit, hint::unreachable_unchecked, marker::PhantomData, ptr::NonNull,
+    fmt,
+    hash::{BuildHasher, BuildHasherDefault, Hash},
+    mem, ops,
 };
 
-// TODO: restructure?
-//       Make Trie always a BumpVec.
-//       Entry has ptr field which becomes new enum type, Ptr
-//       Ptr can be Data or Trie.
-//       Removes some unnecessary matching I think?
+/// A nested hash trie, flattened into one array.
+///
+/// Every per-node hash map lives in `entries`, packed level by level: all of
+/// the level 0 maps (just the root), then all of the level 1 maps, and so on.
+/// Entries point at their child map by offset into that array, or at their
+/// leaf by index into `data`.
+///
+/// `S` decides whether inserting a tuple twice keeps one leaf ([`Set`]) or
+/// counts the insertions on the leaf ([`crate::leaf::Bag`]). `B` hashes keys;
+/// tries can only be intersected if they were built with the same hasher.
+///
+/// A trie is [`Send`] and [`Sync`] whenever its keys and hasher are, so one
+/// trie can be read from several threads at once.
 #[derive(Debug)]
-pub enum Trie<'b, E, const N: usize> {
-    Empty,
-    Data(NonNull<Data<'b, [E; N]>>),
-    // Data(E),
-    // Map(Box<'b, [Entry<'b, E, N>]>),
-    Map(BumpVec<'b, Entry<'b, E, N>>),
+pub struct Trie<'b, E, const N: usize, S: Semantics = Set, B = BuildHasherDefault<ahash::AHasher>> {
+    entries: BumpVec<'b, Entry>,
+    /// Offset in `entries` at which each level's maps start.
+    levels: [usize; N],
+    data: BumpVec<'b, Data<[E; N], S>>,
+    root: Ptr,
+    hasher: B,
 }
 
-impl<'b, E, const N: usize> Default for Trie<'b, E, N> {
-    fn default() -> Self {
-        Trie::Empty
-    }
+/// How many buckets [`Trie`] gives each node's map while building.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sizing {
+    /// Count every node's keys exactly, in one pass over the tuples grouped
+    /// by hash, so that no map ever has to grow.
+    #[default]
+    Exact,
+    /// Size every map at a level for a HyperLogLog estimate of the distinct
+    /// values in that whole column, with the given precision (4 to 18).
+    /// Skips the grouping, but gives each child as many buckets as its
+    /// whole level, and maps which turn out too small have to grow.
+    Estimate { precision: u8 },
 }
 
-#[derive(Debug)]
-pub struct Entry<'b, E, const N: usize> {
+/// A bucket in one of the trie's hash maps: the key's hash, plus a pointer
+/// to whatever is under it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
     hash: u64,
-    // TODO: Could replace this with Option<Trie<'b, E, N>> and
-    //       remove Trie::Empty
-    //       Depends on if this makes the enum smaller
-    ptr: Trie<'b, E, N>,
+    ptr: Ptr,
 }
 
-impl<'b, E, const N: usize> Default for Entry<'b, E, N> {
-    fn default() -> Self {
-        Entry {
-            hash: 0,
-            ptr: Trie::Empty,
+/// A tagged pointer, distinguished by its two low bits:
+///
+/// ```text
+/// 0000...000000 => empty
+/// xxxx...xxxx01 => data: index into `data`
+/// yyyy...zzzz10 => map: offset into `entries` (y) and log2 of its size (z)
+/// ```
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+pub struct Ptr(u64);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Empty,
+    Data(usize),
+    Map { offset: usize, bits: u32 },
+}
+
+impl Ptr {
+    const EMPTY: Self = Ptr(0);
+    const TAG_DATA: u64 = 0b01;
+    const TAG_MAP: u64 = 0b10;
+
+    fn data(ix: usize) -> Self {
+        Ptr((ix as u64) << 2 | Self::TAG_DATA)
+    }
+
+    fn map(offset: usize, bits: u32) -> Self {
+        debug_assert!(bits < 64);
+        Ptr((offset as u64) << 8 | (bits as u64) << 2 | Self::TAG_MAP)
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    #[inline]
+    fn get(&self) -> Node {
+        match self.0 & 0b11 {
+            Self::TAG_DATA => Node::Data((self.0 >> 2) as usize),
+            Self::TAG_MAP => Node::Map {
+                offset: (self.0 >> 8) as usize,
+                bits: ((self.0 >> 2) & 0b11_1111) as u32,
+            },
+            _ => Node::Empty,
         }
     }
 }
 
+impl fmt::Debug for Ptr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.get(), f)
+    }
+}
+
+/// A leaf: one distinct tuple, plus however many times it was inserted.
 #[derive(Debug)]
-pub struct Data<'b, T> {
+pub struct Data<T, S: Semantics = Set> {
     data: T,
-    next: Option<NonNull<Self>>,
-    _p: PhantomData<&'b T>,
+    count: S::Count,
+}
+
+impl<T, S: Semantics> Data<T, S> {
+    fn new(data: T) -> Self {
+        Data {
+            data,
+            count: S::one(),
+        }
+    }
+
+    #[inline]
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// The multiplicity of this tuple; always 1 under set semantics.
+    #[inline]
+    pub fn count(&self) -> usize {
+        S::get(&self.count)
+    }
 }
 
-impl<'b, E, const N: usize> Trie<'b, E, N>
+impl<'b, E, const N: usize, S: Semantics, B> Trie<'b, E, N, S, B>
 where
     E: Hash,
+    B: BuildHasher,
 {
-    fn init_and_get<H: Hasher + Default>(
-        &mut self,
-        key: &E,
-        bits: u32,
-        bump: &'b Bump,
-    ) -> Option<&mut Self> {
-        match self {
-            Trie::Empty => {
-                // Turn this into a map
-                *self = Self::map(bits, bump);
-
-                let (hash, bucket_ix) = Self::get_bucket::<H>(key, bits);
-                let bv = match self {
-                    Trie::Map(bv) => bv,
-                    _ => unsafe { unreachable_unchecked() },
-                };
-                let res = &mut bv[bucket_ix];
-                res.hash = hash;
+    /// The map at `offset`, of `2 ^ bits` buckets.
+    #[inline]
+    fn map_at(&self, offset: usize, bits: u32) -> &[Entry] {
+        &self.entries[offset..offset + (1 << bits)]
+    }
 
-                Some(&mut res.ptr)
-            }
-            Trie::Map(bv) => {
-                // Linear probe
-                let (hash, mut bucket_ix) = Self::get_bucket::<H>(key, bits);
-                let max_ix = bv.len() - 1;
-
-                loop {
-                    let cur = &mut bv[bucket_ix];
-
-                    if bucket_ix == max_ix {
-                        // This ix is ok if we have no other options lmao
-                        break;
-                    } else if let Trie::Empty = cur.ptr {
-                        // This ix is ok if it's unoccupied
-                        break;
-                    } else if cur.hash == hash {
-                        // This ix is ok if it's the same hash
-                        break;
-                    }
+    fn root_map(&self) -> &[Entry] {
+        match self.root.get() {
+            Node::Map { offset, bits } => self.map_at(offset, bits),
+            _ => &[],
+        }
+    }
 
-                    bucket_ix += 1;
-                }
+    /// The hasher this trie was built with; build other tries with a clone
+    /// of it to intersect against this one.
+    #[inline]
+    pub fn hasher(&self) -> &B {
+        &self.hasher
+    }
 
-                bv[bucket_ix].hash = hash;
-                Some(&mut bv[bucket_ix].ptr)
-            }
-            // TODO: with singleton opt, this would mean something i think
-            //       let's say we have this map:
-            //       h(1) -> (1, 2)
-            //       then we try to insert (1, 3)
-            //       this means we need to do the following:
-            //       1. set self to a map
-            //       2. reinsert the data ptr that was here to the correct position on the new map
-            //          corresponding to the hash val of the next level (we can get the val from the data
-            //          ptr and then hash it)
-            //       3. hash whatever we're tryna insert
-            //       idk how this is perf wise
-            Trie::Data(_) => None,
-        }
+    /// Every distinct tuple in the trie, along with its multiplicity.
+    #[inline]
+    pub fn leaves(&self) -> &[Data<[E; N], S>] {
+        &self.data
     }
 
-    fn print_util(&self) {
-        match self {
-            Trie::Map(vs) => {
-                let occ = vs.iter().filter(|x| if let Trie::Empty = x.ptr { false } else { true }).count();
-                println!("{} / {}", occ, vs.len());
-            },
-            _ => {},
-            
-        }
+    /// Linear probe, wrapping around the end of the map, for the first bucket
+    /// which is either unoccupied or already holds `hash` along with a key
+    /// which `is` accepts, given the bucket's index.
+    /// Returns `None` if the map is full of other keys.
+    fn probe(map: &[Entry], hash: u64, is: impl Fn(usize) -> bool) -> Option<usize> {
+        let mask = map.len() - 1;
+        let start = Self::bucket_of(hash, map.len().trailing_zeros());
+
+        (0..map.len())
+            .map(|i| (start + i) & mask)
+            .find(|&ix| map[ix].ptr.is_empty() || map[ix].hash == hash && is(ix))
+    }
+
+    /// Like `probe`, but only returns buckets which actually hold `hash`.
+    fn find(map: &[Entry], hash: u64, is: impl Fn(usize) -> bool) -> Option<usize> {
+        Self::probe(map, hash, is).filter(|&ix| !map[ix].ptr.is_empty())
     }
 
-    fn map(bits: u32, bump: &'b Bump) -> Self {
-        let buckets = 2_usize.pow(bits);
-        let mut bv = BumpVec::with_capacity_in(buckets, bump);
-        for _i in 0..buckets {
-            bv.push(Entry::default(), bump);
+    /// Looks up the bucket of `map`, a map at `level`, holding the key which
+    /// hashes to `hash`. Distinct keys can share a hash, so the bucket's
+    /// value has to match `key` too.
+    fn lookup<'k>(
+        &self,
+        map: &[Entry],
+        level: usize,
+        hash: u64,
+        key: impl FnOnce() -> &'k E,
+    ) -> Option<usize>
+    where
+        E: Eq + 'k,
+    {
+        let key = key();
+        Self::find(map, hash, |ix| {
+            &self.first_leaf(map[ix].ptr).data[level] == key
+        })
+    }
+
+    /// Moves a full map to a new region at the end of its level with twice
+    /// as many buckets, rehashing its entries. The old region is left empty,
+    /// to be dropped when the levels are packed.
+    fn grow(level: &mut impl Level, firsts: &mut Vec<usize>, offset: usize, bits: u32) -> Ptr {
+        let ptr = level.new_map(bits + 1);
+        firsts.resize(level.len(), 0);
+        let Node::Map {
+            offset: new_offset, ..
+        } = ptr.get()
+        else {
+            unreachable!()
+        };
+
+        for i in offset..offset + (1 << bits) {
+            let e = mem::take(&mut level[i]);
+            let new = &mut level[new_offset..];
+            // Colliding keys share a hash, so only stop at an empty bucket
+            let ix = Self::probe(new, e.hash, |_| false).unwrap();
+            new[ix] = e;
+            firsts[new_offset + ix] = firsts[i];
         }
 
-        Self::Map(bv)
+        ptr
     }
 
     fn calc_bits(iter_len: usize) -> u32 {
@@ -149,173 +245,968 @@ where
         (sz * 1.25).log2().ceil() as u32
     }
 
-    fn get_bucket<H: Hasher + Default>(elem: &E, bits: u32) -> (u64, usize) {
-        let mut h = H::default();
-        elem.hash(&mut h);
-        let v = h.finish();
-        (
-            v,
-            (v >> (usize::BITS - bits as u32) as u64)
-                .try_into()
-                .unwrap(),
-        )
+    /// Buckets are picked using the top `bits` bits of the hash.
+    #[inline]
+    fn bucket_of(hash: u64, bits: u32) -> usize {
+        hash.checked_shr(u64::BITS - bits).unwrap_or(0) as usize
+    }
+}
+
+impl<'b, E, const N: usize, S: Semantics, B> Trie<'b, E, N, S, B> {
+    /// The map `ptr` points at, or no buckets at all if it's a leaf.
+    #[inline]
+    fn map_of(&self, ptr: Ptr) -> &[Entry] {
+        match ptr.get() {
+            Node::Map { offset, bits } => &self.entries[offset..offset + (1 << bits)],
+            _ => &[],
+        }
+    }
+
+    /// Any one of the leaves under `ptr`. Every leaf under a key agrees on
+    /// that key's value, so this is how we get keys back out of hashes.
+    fn first_leaf(&self, mut ptr: Ptr) -> &Data<[E; N], S> {
+        loop {
+            match ptr.get() {
+                Node::Data(d) => return &self.data[d],
+                // Maps are only ever created to be inserted into, so they
+                // always have an occupied bucket
+                Node::Map { .. } => {
+                    ptr = self
+                        .map_of(ptr)
+                        .iter()
+                        .find(|e| !e.ptr.is_empty())
+                        .unwrap()
+                        .ptr
+                }
+                Node::Empty => unreachable!(),
+            }
+        }
+    }
+}
+
+/// One level's maps while building a [`Trie`]. Child pointers address maps
+/// by offset from the start of their level until the levels are packed.
+trait Level: ops::DerefMut<Target = [Entry]> {
+    /// Allocates a new, empty map of `2 ^ bits` buckets after the level's
+    /// other maps.
+    fn new_map(&mut self, bits: u32) -> Ptr;
+}
+
+impl Level for Vec<Entry> {
+    fn new_map(&mut self, bits: u32) -> Ptr {
+        let offset = self.len();
+        self.resize(offset + (1 << bits), Entry::default());
+        Ptr::map(offset, bits)
     }
 }
 
-impl<'b, E, const N: usize> Oneshot<'b, N> for Trie<'b, E, N>
+/// A level sized exactly up front, laid out in its final place in the
+/// trie's `entries`. Its maps never grow, so it never runs out of room.
+struct Presized<'e> {
+    maps: &'e mut [Entry],
+    used: usize,
+}
+
+impl Level for Presized<'_> {
+    fn new_map(&mut self, bits: u32) -> Ptr {
+        let offset = self.used;
+        self.used += 1 << bits;
+        assert!(self.used <= self.maps.len(), "level was sized too small");
+        Ptr::map(offset, bits)
+    }
+}
+
+impl ops::Deref for Presized<'_> {
+    type Target = [Entry];
+
+    fn deref(&self) -> &[Entry] {
+        self.maps
+    }
+}
+
+impl ops::DerefMut for Presized<'_> {
+    fn deref_mut(&mut self) -> &mut [Entry] {
+        self.maps
+    }
+}
+
+/// A [`crate::Cursor`] into a [`Trie`]: the map at this node, or the leaf
+/// once we've advanced past every level.
+pub struct Cursor<'a, 'b, E, const N: usize, S: Semantics, B> {
+    trie: &'a Trie<'b, E, N, S, B>,
+    ptr: Ptr,
+    depth: usize,
+}
+
+impl<'a, 'b, E, const N: usize, S: Semantics, B> Cursor<'a, 'b, E, N, S, B>
+where
+    E: Hash + Eq,
+    B: BuildHasher,
+{
+    /// The cursor under `key`, which hashes to `hash`, if there is one.
+    fn child(&self, hash: u64, key: &E) -> Option<Self> {
+        let map = self.trie.map_of(self.ptr);
+        if map.is_empty() {
+            return None;
+        }
+
+        let ix = self.trie.lookup(map, self.depth, hash, || key)?;
+        Some(Self {
+            ptr: map[ix].ptr,
+            depth: self.depth + 1,
+            ..*self
+        })
+    }
+}
+
+impl<'a, 'b, E, const N: usize, S: Semantics, B> Clone for Cursor<'a, 'b, E, N, S, B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, 'b, E, const N: usize, S: Semantics, B> Copy for Cursor<'a, 'b, E, N, S, B> {}
+
+impl<'a, 'b: 'a, E, const N: usize, S: Semantics, B> crate::Cursor<'a>
+    for Cursor<'a, 'b, E, N, S, B>
+where
+    E: Hash + Eq + 'b,
+    B: BuildHasher,
+{
+    type Value = E;
+    type Keys = iter_type!('a, &'a E);
+    type Intersect<const M: usize> = iter_type!('a, (&'a E, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn len(&self) -> usize {
+        self.trie
+            .map_of(self.ptr)
+            .iter()
+            .filter(|e| !e.ptr.is_empty())
+            .count()
+    }
+
+    /// The buckets in this node's map, occupied or not.
+    #[inline]
+    fn len_hint(&self) -> usize {
+        self.trie.map_of(self.ptr).len()
+    }
+
+    fn keys(&self) -> Self::Keys {
+        let (trie, depth) = (self.trie, self.depth);
+        trie.map_of(self.ptr)
+            .iter()
+            .filter(|e| !e.ptr.is_empty())
+            .map(move |e| &trie.first_leaf(e.ptr).data[depth])
+            .into_iter_type()
+    }
+
+    fn advance(&self, v: &E) -> Option<Self> {
+        self.child(self.trie.hasher.hash_one(v), v)
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        let this = *self;
+
+        this.trie
+            .map_of(this.ptr)
+            .iter()
+            .filter(|e| !e.ptr.is_empty())
+            .filter_map(move |e| {
+                let key = &this.trie.first_leaf(e.ptr).data[this.depth];
+                let children = crate::try_from_fn(|i| others[i].child(e.hash, key))?;
+
+                let child = Self {
+                    ptr: e.ptr,
+                    depth: this.depth + 1,
+                    ..this
+                };
+                Some((key, child, children))
+            })
+            .into_iter_type()
+    }
+}
+
+impl<'b, E, const N: usize, S: Semantics, B> Oneshot<'b, N> for Trie<'b, E, N, S, B>
 where
-    E: Clone + Hash + std::fmt::Debug,
+    E: Clone + Hash + Eq + std::fmt::Debug,
+    B: BuildHasher + Default + Clone + 'b,
 {
     type Value = E;
     type IVal = usize;
-    type KeyIter<const M: usize> = impl Iterator<Item = usize> where Self: 'b;
+    type KeyIter<const M: usize>
+        = iter_type!('b, usize)
+    where
+        Self: 'b;
+    type Cursor<'a>
+        = Cursor<'a, 'b, E, N, S, B>
+    where
+        Self: 'a;
 
     fn from_iter<I: IntoIterator<Item = [Self::Value; N]>>(iter: I, bump: &'b Bump) -> Self {
-        // Collect into a vec first (lmao)
-        let iter = iter.into_iter().collect::<Vec<_>>();
-
-        // HLL cardinality estimation
-        let mut hlls: [HyperLogLogPF<E, ahash::RandomState>; N] = unsafe {
-            let mut arr: [_; N] = MaybeUninit::uninit().assume_init();
-            for item in &mut arr[..] {
-                std::ptr::write(
-                    item,
-                    HyperLogLogPF::new(4, ahash::RandomState::new()).unwrap(),
-                );
+        Self::from_iter_with_hasher(iter, B::default(), bump)
+    }
+
+    fn open(&self) -> Self::Cursor<'_> {
+        Cursor {
+            trie: self,
+            ptr: self.root,
+            depth: 0,
+        }
+    }
+
+    fn intersect<const M: usize>(&'b self, others: [&'b Self; M]) -> Self::KeyIter<M> {
+        let mut vals = self.root_map().iter().enumerate();
+
+        std::iter::from_fn(move || 'outer: loop {
+            if let Some((ix, v)) = vals.next() {
+                if v.ptr.is_empty() {
+                    continue 'outer;
+                }
+
+                let key = || &self.first_leaf(v.ptr).data[0];
+                for other in others.iter() {
+                    if other.lookup(other.root_map(), 0, v.hash, key).is_none() {
+                        continue 'outer;
+                    }
+                }
+
+                return Some(ix);
+            } else {
+                return None;
             }
-            arr
-        };
+        })
+        .into_iter_type()
+    }
+}
 
-        for tup in iter.iter() {
-            for (v, c) in tup.iter().zip(hlls.iter_mut()) {
-                c.insert(v);
+impl<'b, E, const N: usize, S: Semantics, B> HashOneshot<'b, N> for Trie<'b, E, N, S, B>
+where
+    E: Clone + Hash + Eq + std::fmt::Debug,
+    B: BuildHasher + Default + Clone + 'b,
+{
+    type BuildHasher = B;
+
+    fn from_iter_with_hasher<I: IntoIterator<Item = [E; N]>>(
+        iter: I,
+        hasher: B,
+        bump: &'b Bump,
+    ) -> Self {
+        Self::from_iter_sized(iter, Sizing::default(), hasher, bump)
+    }
+}
+
+impl<'b, E, const N: usize, S: Semantics, B> TryOneshot<'b, N> for Trie<'b, E, N, S, B>
+where
+    E: Clone + Hash + Eq + std::fmt::Debug,
+    B: BuildHasher + Default + Clone + 'b,
+{
+    fn try_from_iter<I: IntoIterator<Item = [E; N]>>(
+        iter: I,
+        bump: &'b Bump,
+    ) -> Result<Self, CollectionAllocError> {
+        Self::try_from_iter_sized(iter, Sizing::default(), B::default(), bump)
+    }
+}
+
+impl<'b, E, const N: usize, S: Semantics, B> Trie<'b, E, N, S, B>
+where
+    E: Clone + Hash + Eq + std::fmt::Debug,
+    B: BuildHasher + Clone,
+{
+    /// Builds a trie, giving each node's map as many buckets as `sizing`
+    /// says.
+    pub fn from_iter_sized<I: IntoIterator<Item = [E; N]>>(
+        iter: I,
+        sizing: Sizing,
+        hasher: B,
+        bump: &'b Bump,
+    ) -> Self {
+        Self::try_from_iter_sized(iter, sizing, hasher, bump).unwrap_or_else(|e| e.raise())
+    }
+
+    /// Like [`Trie::from_iter_sized`], but fails instead of panicking if the
+    /// arena can't hold the leaves and maps.
+    pub fn try_from_iter_sized<I: IntoIterator<Item = [E; N]>>(
+        iter: I,
+        sizing: Sizing,
+        hasher: B,
+        bump: &'b Bump,
+    ) -> Result<Self, CollectionAllocError> {
+        // Keys are looked up by their hashes at every level, so hash
+        // everything once up front
+        let mut tuples = iter
+            .into_iter()
+            .map(|t| Self::hashed(&hasher, t))
+            .collect::<Vec<_>>();
+
+        // There's at most a leaf per tuple, so once reserved, pushing leaves
+        // won't allocate
+        let mut data: BumpVec<Data<[E; N], S>> = BumpVec::try_with_capacity_in(tuples.len(), bump)?;
+
+        let (entries, starts, root) = match sizing {
+            Sizing::Exact => {
+                Self::group(&mut tuples);
+                Self::exact(tuples, &mut data, bump)?
             }
-        }
+            Sizing::Estimate { precision } => {
+                let estimate = Self::estimate_bits(&tuples, precision, &hasher);
 
-        let cardinalities: [usize; N] = unsafe {
-            let mut arr: [_; N] = MaybeUninit::uninit().assume_init();
-            for (item, hll) in (&mut arr[..]).into_iter().zip(hlls.iter_mut()) {
-                std::ptr::write(item, hll.count().trunc() as usize);
+                // We don't know how many maps each level will need until
+                // we're done, so build each level separately and pack them
+                // together at the end.
+                let mut levels: [Vec<Entry>; N] = std::array::from_fn(|_| Vec::new());
+                let root = Self::insert(&mut levels, tuples, |l| estimate[l], &mut data, bump);
+                Self::pack(&levels, root, bump)?
             }
-            arr
         };
 
-        let mut res = Trie::Empty;
+        Ok(Self {
+            entries,
+            levels: starts,
+            data,
+            root,
+            hasher,
+        })
+    }
+
+    fn hashed(hasher: &B, tuple: [E; N]) -> ([u64; N], [E; N]) {
+        (std::array::from_fn(|l| hasher.hash_one(&tuple[l])), tuple)
+    }
+
+    /// Builds the levels for [`Sizing::Exact`] from `tuples`, which must be
+    /// [`Trie::group`]ed, and returns them with each level's start and the
+    /// root.
+    fn exact(
+        tuples: Vec<([u64; N], [E; N])>,
+        data: &mut BumpVec<'b, Data<[E; N], S>>,
+        bump: &'b Bump,
+    ) -> Result<(BumpVec<'b, Entry>, [usize; N], Ptr), CollectionAllocError> {
+        let mut exact = Self::exact_bits(&tuples);
+
+        // Every map's size is known, so lay the levels out one after
+        // another in the arena, and build each in its final place
+        let sizes = exact
+            .each_ref()
+            .map(|b| b.as_slice().iter().map(|&b| 1 << b).sum());
+        let mut starts = [0; N];
+        for l in 1..N {
+            starts[l] = starts[l - 1] + sizes[l - 1];
+        }
+        let total = sizes.iter().sum();
+        let mut entries = BumpVec::try_with_capacity_in(total, bump)?;
+        entries.resize(total, Entry::default(), bump);
+
+        let mut rest = &mut entries[..];
+        let mut levels = sizes.map(|size| {
+            let (maps, tail) = mem::take(&mut rest).split_at_mut(size);
+            rest = tail;
+            Presized { maps, used: 0 }
+        });
+        let root = Self::insert(
+            &mut levels,
+            tuples,
+            |l| exact[l].next().unwrap(),
+            data,
+            bump,
+        );
+
+        // Rebase child map offsets (which are relative to their own level)
+        // onto the whole array
+        for l in 0..N - 1 {
+            for e in entries[starts[l]..starts[l + 1]].iter_mut() {
+                if let Node::Map { offset, bits } = e.ptr.get() {
+                    e.ptr = Ptr::map(starts[l + 1] + offset, bits);
+                }
+            }
+        }
+        Ok((entries, starts, root))
+    }
+
+    /// Inserts every tuple into `levels`, giving each new map at level `l`
+    /// `bits_of(l)` bits, and returns the root.
+    fn insert<L: Level>(
+        levels: &mut [L; N],
+        tuples: Vec<([u64; N], [E; N])>,
+        mut bits_of: impl FnMut(usize) -> u32,
+        data: &mut BumpVec<'b, Data<[E; N], S>>,
+        bump: &'b Bump,
+    ) -> Ptr {
+        let mut root = Ptr::EMPTY;
+        // The leaf each entry was first inserted for, by level and index,
+        // which tells us the value of the entry's key
+        let mut firsts: [Vec<usize>; N] = std::array::from_fn(|_| Vec::new());
+
+        for (hashes, tup) in tuples {
+            // The (level, index) of the entry we just descended through.
+            // None means we're at the root.
+            let mut parent: Option<(usize, usize)> = None;
+
+            for (l, &hash) in hashes.iter().enumerate() {
+                let node = match parent {
+                    None => root,
+                    Some((pl, pix)) => levels[pl][pix].ptr,
+                };
+
+                let mut ptr = match node.get() {
+                    Node::Empty => {
+                        let ptr = levels[l].new_map(bits_of(l));
+                        firsts[l].resize(levels[l].len(), 0);
+                        ptr
+                    }
+                    Node::Map { .. } => node,
+                    // Every tuple has exactly N values
+                    Node::Data(_) => unreachable!(),
+                };
+
+                let ix = loop {
+                    let (offset, bits) = match ptr.get() {
+                        Node::Map { offset, bits } => (offset, bits),
+                        _ => unreachable!(),
+                    };
+
+                    let map = &levels[l][offset..offset + (1 << bits)];
+                    // A key with this hash may be a different value which
+                    // collided with ours; probe past it
+                    let found = Self::probe(map, hash, |ix| {
+                        data[firsts[l][offset + ix]].data[l] == tup[l]
+                    });
+                    match found {
+                        Some(ix) => break offset + ix,
+                        // Every bucket is taken by some other key; make room
+                        None => ptr = Self::grow(&mut levels[l], &mut firsts[l], offset, bits),
+                    }
+                };
 
-        for tup in iter {
-            let mut cur = &mut res;
+                match parent {
+                    None => root = ptr,
+                    Some((pl, pix)) => levels[pl][pix].ptr = ptr,
+                }
 
-            let mut it = tup.iter().zip(cardinalities.iter());
-            while let Some((v, buckets)) = it.next() {
-                cur = cur
-                    .init_and_get::<ahash::AHasher>(v, Self::calc_bits(*buckets), bump)
-                    .unwrap();
+                if levels[l][ix].ptr.is_empty() {
+                    // A new key, so this tuple is about to become a new leaf
+                    firsts[l][ix] = data.len();
+                }
+                levels[l][ix].hash = hash;
+                parent = Some((l, ix));
             }
 
-            // At this point cur is pointing to an entry
+            // At this point parent is pointing to an entry
             // that should become a data ptr.
-            let d = Box::new_in(
-                Data {
-                    data: tup,
-                    next: None,
-                    _p: PhantomData,
-                },
-                bump,
-            );
-            match cur {
-                Trie::Empty => {
-                    *cur = Trie::Data(NonNull::from(Box::leak(d)));
+            let (l, ix) = parent.expect("can't build a trie of empty tuples");
+            match levels[l][ix].ptr.get() {
+                Node::Empty => {
+                    data.push(Data::new(tup), bump);
+                    levels[l][ix].ptr = Ptr::data(data.len() - 1);
                 }
-                Trie::Data(pd) => {
-                    let mut t = unsafe { pd.as_mut() };
-                    while t.next.as_mut().is_some() {
-                        // We can't use while let here bc of borrowck limits
-                        // with linked lists
-                        // fuckin rust smfh
-                        t = unsafe { t.next.unwrap().as_mut() };
-                    }
-                    t.next = Some(NonNull::from(Box::leak(d)));
+                Node::Data(d) => {
+                    // The leaf holds this very tuple, so record the
+                    // duplicate on it.
+                    S::bump(&mut data[d].count);
                 }
-                Trie::Map(_) => unsafe { unreachable_unchecked() },
+                Node::Map { .. } => unreachable!(),
             }
         }
 
-        res
+        root
     }
 
-    fn advance(&'b self, v: &Self::Value) -> Option<&'b Self> {
-        todo!()
-    }
+    /// Packs `levels` one after another into the arena, rebasing child map
+    /// offsets (which are relative to their own level) onto the packed
+    /// array. Only the maps reachable from `root` are kept, so the regions
+    /// which grown maps left behind are dropped.
+    fn pack(
+        levels: &[Vec<Entry>; N],
+        root: Ptr,
+        bump: &'b Bump,
+    ) -> Result<(BumpVec<'b, Entry>, [usize; N], Ptr), CollectionAllocError> {
+        // The live maps at each level, in the order their parents point at
+        // them, as (offset, bits)
+        let mut maps: [Vec<(usize, u32)>; N] = std::array::from_fn(|_| Vec::new());
+        if let Node::Map { offset, bits } = root.get() {
+            maps[0].push((offset, bits));
+        }
+        for l in 1..N {
+            let (above, below) = maps.split_at_mut(l);
+            for &(offset, bits) in &above[l - 1] {
+                for e in &levels[l - 1][offset..offset + (1 << bits)] {
+                    if let Node::Map { offset, bits } = e.ptr.get() {
+                        below[0].push((offset, bits));
+                    }
+                }
+            }
+        }
+
+        let sizes = maps
+            .each_ref()
+            .map(|m| m.iter().map(|&(_, bits)| 1 << bits).sum());
+        let mut starts = [0; N];
+        for l in 1..N {
+            starts[l] = starts[l - 1] + sizes[l - 1];
+        }
+        let mut entries = BumpVec::try_with_capacity_in(sizes.iter().sum(), bump)?;
+
+        for l in 0..N {
+            // Where the next child map goes at the level below
+            let mut child = starts.get(l + 1).copied().unwrap_or(0);
+            for &(offset, bits) in &maps[l] {
+                for e in &levels[l][offset..offset + (1 << bits)] {
+                    let ptr = match e.ptr.get() {
+                        Node::Map { bits, .. } => {
+                            child += 1 << bits;
+                            Ptr::map(child - (1 << bits), bits)
+                        }
+                        _ => e.ptr,
+                    };
+                    entries.push(Entry { hash: e.hash, ptr }, bump);
+                }
+            }
+        }
 
-    fn intersect<'a, const M: usize>(&'b self, others: [&'b Self; M]) -> Self::KeyIter<M> {
-        let mut vals = match self {
-            Trie::Empty => todo!(),
-            Trie::Data(_) => todo!(),
-            Trie::Map(vs) => vs.iter().enumerate(),
+        // The root map is the only one at level 0
+        let root = match root.get() {
+            Node::Map { bits, .. } => Ptr::map(0, bits),
+            _ => root,
         };
+        Ok((entries, starts, root))
+    }
 
-        std::iter::from_fn(move || {
-            'outer: loop {
-                if let Some((ix, v)) = vals.next() {
-                    if let Trie::Empty = v.ptr {
-                        continue 'outer;
-                    }
+    /// Sorts `tuples` by hash, then regroups any run of tuples under one
+    /// node whose keys collide by the keys' values, so that every node's
+    /// tuples end up next to each other.
+    fn group(tuples: &mut [([u64; N], [E; N])]) {
+        tuples.sort_unstable_by_key(|(hashes, _)| *hashes);
 
-                    for other in others.iter() {
-                        let other = match other {
-                            Trie::Empty => todo!(),
-                            Trie::Data(_) => todo!(),
-                            Trie::Map(os) => os,
-                        };
-                        let other_bits = other.len().log2();
-                        let mut other_ix = (v.hash >> (usize::BITS - other_bits)) as usize;
-
-                        // Start linear probe search
-                        let max_ix = other.len() - 1;
-
-                        loop {
-                            let cur = &other[other_ix];
-
-                            if other_ix == max_ix {
-                                // Couldn't find before end
-                                continue 'outer;
-                            } else if let Trie::Empty = cur.ptr {
-                                // Couldn't find before empty
-                                continue 'outer;
-                            } else if cur.hash == v.hash {
-                                // This ix is ok if it's the same hash
-                                break;
-                            }
-
-                            other_ix += 1;
-                        }
+        for l in 0..N {
+            let mut start = 0;
+            while start < tuples.len() {
+                let (hashes, tup) = &tuples[start];
+                let len = tuples[start..]
+                    .iter()
+                    .take_while(|(h, t)| h[..=l] == hashes[..=l] && t[..l] == tup[..l])
+                    .count();
+                let run = &mut tuples[start..start + len];
+                start += len;
+                if run.iter().all(|(_, t)| t[l] == run[0].1[l]) {
+                    continue;
+                }
+
+                // Collisions are rare, so there are only ever a few values
+                let mut values: Vec<E> = Vec::new();
+                for (_, t) in run.iter() {
+                    if !values.contains(&t[l]) {
+                        values.push(t[l].clone());
                     }
+                }
+                // Stable, so each value's tuples stay sorted by hash
+                run.sort_by_cached_key(|(_, t)| values.iter().position(|v| *v == t[l]));
+            }
+        }
+    }
 
-                    return Some(ix)
-                } else {
-                    return None;
+    /// The bits of every map each level will need, in the order they're
+    /// created by inserting `tuples`, which must be [`Trie::group`]ed.
+    fn exact_bits(tuples: &[([u64; N], [E; N])]) -> [std::vec::IntoIter<u32>; N] {
+        let mut fanouts: [Vec<usize>; N] = std::array::from_fn(|_| Vec::new());
+
+        for (i, (hashes, tup)) in tuples.iter().enumerate() {
+            // The first level at which this tuple parts from the last one.
+            // Below it every node is new, and from it every key is.
+            let (first, new_nodes) = match i.checked_sub(1).map(|p| &tuples[p]) {
+                None => (0, 0),
+                Some((ph, pt)) => {
+                    match (0..N).position(|l| ph[l] != hashes[l] || pt[l] != tup[l]) {
+                        Some(l) => (l, l + 1),
+                        // A duplicate
+                        None => continue,
+                    }
                 }
+            };
+
+            for f in fanouts[new_nodes..].iter_mut() {
+                f.push(0);
+            }
+            for f in fanouts[first..].iter_mut() {
+                *f.last_mut().unwrap() += 1;
             }
+        }
+
+        fanouts.map(|f| {
+            f.into_iter()
+                .map(Self::calc_bits)
+                .collect::<Vec<_>>()
+                .into_iter()
         })
     }
+
+    /// The bits of every map at each level, from a HyperLogLog estimate of
+    /// each column's distinct values.
+    fn estimate_bits(tuples: &[([u64; N], [E; N])], precision: u8, hasher: &B) -> [u32; N] {
+        let mut hlls: [HyperLogLogPF<E, B>; N] = std::array::from_fn(|_| {
+            HyperLogLogPF::new(precision, hasher.clone())
+                .expect("HyperLogLog precision must be from 4 to 18")
+        });
+
+        for (_, tup) in tuples {
+            for (v, c) in tup.iter().zip(hlls.iter_mut()) {
+                c.insert(v);
+            }
+        }
+
+        hlls.map(|mut hll| Self::calc_bits(hll.count().trunc() as usize))
+    }
+}
+
+impl<'b, E, const N: usize, S: Semantics, B> Trie<'b, E, N, S, B>
+where
+    E: Clone + Hash + Eq + Send + std::fmt::Debug,
+    B: BuildHasher + Clone + Sync,
+{
+    /// Builds the same trie as [`Trie::from_iter_sized`] with
+    /// [`Sizing::Exact`], with one worker per arena in `arenas`; the calling
+    /// thread is the first.
+    ///
+    /// The workers hash the tuples, then split them by the hash of their
+    /// first value, so that every child of the root is grouped whole by one
+    /// worker. The grouped shares come out in hash order, so the maps and
+    /// leaves are laid out from them in the first arena, as the sequential
+    /// build would. The other arenas go unused, as the trie is one array.
+    ///
+    /// Panics if `arenas` is empty, or if the first arena can't hold the
+    /// trie. A panic while hashing or grouping a share, say from `E`'s
+    /// [`Hash`] impl, is passed on after the other shares are done.
+    pub fn par_from_iter<I>(iter: I, hasher: B, arenas: &'b mut [Bump]) -> Self
+    where
+        I: IntoIterator<Item = [E; N]>,
+    {
+        let workers = arenas.len();
+        let tuples = iter.into_iter().collect::<Vec<_>>();
+        let mut tuples = tuples.into_iter();
+        let chunk = tuples.len().div_ceil(workers.max(1));
+        let chunks = (0..workers)
+            .map(|_| tuples.by_ref().take(chunk).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        // Each worker hashes its chunk and deals it out by the root hash,
+        // which is uniform, into one bucket per worker
+        let (_, dealt) = on_workers(&mut *arenas, chunks, |_, chunk| {
+            let mut buckets = (0..workers).map(|_| Vec::new()).collect::<Vec<_>>();
+            for t in chunk {
+                let t = Self::hashed(&hasher, t);
+                buckets[((t.0[0] as u128 * workers as u128) >> 64) as usize].push(t);
+            }
+            buckets
+        });
+        let mut shares = (0..workers).map(|_| Vec::new()).collect::<Vec<_>>();
+        for buckets in dealt {
+            for (share, bucket) in shares.iter_mut().zip(buckets) {
+                share.extend(bucket);
+            }
+        }
+
+        let (bump, grouped) = on_workers(arenas, shares, |_, mut share| {
+            Self::group(&mut share);
+            share
+        });
+        let tuples = grouped.into_iter().flatten().collect::<Vec<_>>();
+
+        let build = || {
+            let mut data = BumpVec::try_with_capacity_in(tuples.len(), bump)?;
+            let (entries, starts, root) = Self::exact(tuples, &mut data, bump)?;
+            Ok(Self {
+                entries,
+                levels: starts,
+                data,
+                root,
+                hasher,
+            })
+        };
+        build().unwrap_or_else(|e: CollectionAllocError| e.raise())
+    }
+}
+
+impl<E, const N: usize, S: Semantics, B> Storable for Trie<'static, E, N, S, B> {
+    type Trie<'bump> = Trie<'bump, E, N, S, B>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'b, E, const N: usize, S: Semantics, B> Stats for Trie<'b, E, N, S, B>
+where
+    E: Clone + Hash + Eq + std::fmt::Debug,
+    B: BuildHasher + Default + Clone + 'b,
+{
+    fn stats(&self) -> TrieStats {
+        let mut stats = TrieStats::walk(self.open(), |_| (0, 0));
+
+        // Count every bucket in each level's region, empty or not
+        for (l, level) in stats.levels.iter_mut().enumerate() {
+            let end = self
+                .levels
+                .get(l + 1)
+                .copied()
+                .unwrap_or(self.entries.len());
+            level.slots = end - self.levels[l];
+        }
+        stats.bytes = self.entries.capacity() * mem::size_of::<Entry>()
+            + self.data.capacity() * mem::size_of::<Data<[E; N], S>>();
+        stats
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::hash::BuildHasherDefault;
+
     use bumpalo::Bump;
 
-    use crate::Oneshot;
+    use crate::{
+        leaf::{Bag, Set},
+        stats::Stats,
+        testing::{self, Colliding},
+        Cursor, HashOneshot, Oneshot,
+    };
 
-    use super::{Trie};
+    use super::{Data, Entry, Node, Sizing, Trie};
 
     #[test]
     fn iter_keys() {
         let a = Bump::new();
-        let t = Trie::from_iter((0..10).map(|x| [x]), &a);
+        let t = Trie::<_, 1>::from_iter((0..10).map(|x| [x]), &a);
 
-        let v: usize = t
-            .intersect([])
-            .count();
+        let v: usize = t.intersect([]).count();
         assert_eq!(v, 10);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn seeded_hasher() {
+        type Seeded<'b> = Trie<'b, i32, 1, Set, ahash::RandomState>;
+        let a = Bump::new();
+        let hasher = ahash::RandomState::with_seeds(1, 2, 3, 4);
+        let t1 = Seeded::from_iter_with_hasher((0..10).map(|x| [x]), hasher.clone(), &a);
+        let t2 = Seeded::from_iter_with_hasher((0..10).step_by(3).map(|x| [x]), hasher, &a);
+
+        assert_eq!(t1.intersect([&t2]).count(), 4);
+    }
+
+    #[test]
+    fn leaf_semantics() {
+        let tuples = [[1, 2], [1, 3], [1, 2], [4, 5], [1, 2]];
+        let a = Bump::new();
+
+        let set = Trie::<_, 2, Set>::from_iter(tuples, &a);
+        assert_eq!(set.data.len(), 3);
+        assert!(set.data.iter().all(|d| d.count() == 1));
+
+        let bag = Trie::<_, 2, Bag>::from_iter(tuples, &a);
+        let mut counts: Vec<_> = bag.data.iter().map(|d| (*d.data(), d.count())).collect();
+        counts.sort();
+        assert_eq!(counts, vec![([1, 2], 3), ([1, 3], 1), ([4, 5], 1)]);
+    }
+
+    #[test]
+    fn collisions() {
+        type Collided<'b, S> = Trie<'b, i32, 2, S, BuildHasherDefault<Colliding>>;
+        let tuples = [[1, 2], [3, 4], [1, 2], [5, 6], [3, 4], [1, 2]];
+        let a = Bump::new();
+
+        // Every key collides, but no tuple is lost or merged with another
+        let bag = Collided::<Bag>::from_iter(tuples, &a);
+        let mut counts: Vec<_> = bag
+            .leaves()
+            .iter()
+            .map(|d| (*d.data(), d.count()))
+            .collect();
+        counts.sort();
+        assert_eq!(counts, vec![([1, 2], 3), ([3, 4], 2), ([5, 6], 1)]);
+        assert_eq!(Collided::<Set>::from_iter(tuples, &a).leaves().len(), 3);
+
+        // and each key only leads to its own tuples
+        let root = bag.open();
+        let mut keys: Vec<_> = root.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, vec![1, 3, 5]);
+
+        let c = root.advance(&1).unwrap();
+        assert_eq!(c.keys().copied().collect::<Vec<_>>(), vec![2]);
+        assert!(c.advance(&4).is_none());
+        assert_eq!(bag.first_leaf(c.advance(&2).unwrap().ptr).count(), 3);
+        assert_eq!(
+            c.intersect_with([c])
+                .map(|(k, _, _)| *k)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        let c = root.advance(&3).unwrap();
+        assert_eq!(c.keys().copied().collect::<Vec<_>>(), vec![4]);
+        assert!(root.advance(&2).is_none());
+        let other = Collided::<Bag>::from_iter([[3, 9], [7, 8]], &a);
+        assert_eq!(bag.intersect([&other]).count(), 1);
+
+        // Maps which grow keep their keys apart too
+        let estimated = Collided::<Bag>::from_iter_sized(
+            tuples,
+            Sizing::Estimate { precision: 4 },
+            Default::default(),
+            &a,
+        );
+        let c = estimated.open().advance(&5).unwrap();
+        assert_eq!(c.keys().copied().collect::<Vec<_>>(), vec![6]);
+    }
+
+    #[test]
+    fn compacted() {
+        type Collided<'b> = Trie<'b, i32, 2, Set, BuildHasherDefault<Colliding>>;
+        let tuples = (0..64).map(|y| [0, y]);
+        let a = Bump::new();
+
+        // The estimate sees a single value in each column, so the one map of
+        // leaves grows over and over; only its last region is kept
+        let t = Collided::from_iter_sized(
+            tuples.clone(),
+            Sizing::Estimate { precision: 12 },
+            Default::default(),
+            &a,
+        );
+        let stats = t.stats();
+        assert_eq!(stats.levels[1].slots, 64);
+        let slots = stats.levels.iter().map(|l| l.slots).sum::<usize>();
+        assert_eq!(
+            stats.bytes,
+            slots * 16 + 64 * std::mem::size_of::<Data<[i32; 2], Set>>()
+        );
+        assert_eq!(t.open().advance(&0).unwrap().keys().count(), 64);
+
+        // Sized exactly, the colliding leaves are counted up front
+        let t = Collided::from_iter(tuples, &a);
+        assert_eq!(t.stats().levels[1].slots, 128);
+        assert_eq!(t.leaves().len(), 64);
+    }
+
+    #[test]
+    fn packed_levels() {
+        assert_eq!(std::mem::size_of::<Entry>(), 16);
+
+        let a = Bump::new();
+        let tuples = (0..20).flat_map(|x| (0..x % 4).map(move |y| [x, y, x + y]));
+        let t = Trie::<_, 3>::from_iter(tuples, &a);
+
+        // Every map pointer in level l points into level l + 1
+        for l in 0..3 {
+            let end = t.levels.get(l + 1).copied().unwrap_or(t.entries.len());
+            for e in t.entries[t.levels[l]..end].iter() {
+                match e.ptr.get() {
+                    Node::Map { offset, bits } => {
+                        assert!(offset >= t.levels[l + 1]);
+                        assert!(
+                            offset + (1 << bits)
+                                <= t.levels.get(l + 2).copied().unwrap_or(t.entries.len())
+                        );
+                    }
+                    Node::Data(_) => assert_eq!(l, 2),
+                    Node::Empty => {}
+                }
+            }
+        }
+        assert_eq!(t.data.len(), (0..20).map(|x| x % 4).sum::<i32>() as usize);
+    }
+
+    #[test]
+    fn sizing() {
+        // One wide node and one narrow one under it
+        let tuples = (0..100).map(|y| [0, y]).chain([[1, 0]]);
+        let a = Bump::new();
+
+        let exact = Trie::<_, 2>::from_iter(tuples.clone(), &a);
+        let stats = exact.stats();
+        assert_eq!(stats.levels[0].slots, 4);
+        assert_eq!(stats.levels[1].slots, 128 + 2);
+
+        let estimated = Trie::<_, 2>::from_iter_sized(
+            tuples,
+            Sizing::Estimate { precision: 12 },
+            Default::default(),
+            &a,
+        );
+        assert!(estimated.stats().levels[1].slots >= 2 * 128);
+
+        let mut keys: Vec<_> = estimated.open().keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, vec![0, 1]);
+        assert_eq!(exact.leaves().len(), estimated.leaves().len());
+    }
+
+    #[test]
+    fn parallel() {
+        // Scrambled, so each worker's share arrives out of order
+        let tuples = (0..6 * 6 * 6 * 6)
+            .map(|i: i32| (i * 631) % 1296)
+            .map(|i| [i % 6, i / 6 % 6, i / 36 % 6, i / 216])
+            .collect::<Vec<_>>();
+        let a = Bump::new();
+        let seq = Trie::<_, 4>::from_iter(tuples.iter().copied(), &a);
+
+        for workers in [1, 2, 4, 8] {
+            let mut arenas = (0..workers).map(|_| Bump::new()).collect::<Vec<_>>();
+            let par = Trie::par_from_iter(tuples.iter().copied(), Default::default(), &mut arenas);
+            assert_eq!((par.levels, par.root), (seq.levels, seq.root));
+            assert_eq!(par.entries[..], seq.entries[..]);
+            let leaves =
+                |t: &Trie<i32, 4>| t.leaves().iter().map(|d| *d.data()).collect::<Vec<_>>();
+            assert_eq!(leaves(&par), leaves(&seq));
+        }
+
+        // Every key collides, so one worker gets everything
+        type Collided<'b> = Trie<'b, i32, 2, Set, BuildHasherDefault<Colliding>>;
+        let tuples = (0..20).map(|x| [x % 3, x]);
+        let mut arenas = (0..4).map(|_| Bump::new()).collect::<Vec<_>>();
+        let par = Collided::par_from_iter(tuples.clone(), Default::default(), &mut arenas);
+        let seq = Collided::from_iter(tuples, &a);
+        assert_eq!(par.entries[..], seq.entries[..]);
+        assert_eq!(par.open().advance(&1).unwrap().len(), 7);
+
+        let mut arenas = (0..4).map(|_| Bump::new()).collect::<Vec<_>>();
+        let empty = Trie::<i32, 2>::par_from_iter([], Default::default(), &mut arenas);
+        assert_eq!(empty.open().len(), 0);
+    }
+
+    #[test]
+    fn cursor() {
+        let a = Bump::new();
+        let t = Trie::<_, 3>::from_iter([[1, 2, 3], [1, 2, 4], [1, 5, 0], [6, 0, 0]], &a);
+        let t2 = Trie::<_, 3>::from_iter([[1, 5, 1], [1, 7, 0]], &a);
+
+        let root = t.open();
+        let mut keys: Vec<_> = root.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, vec![1, 6]);
+
+        let c = root.advance(&1).unwrap();
+        let c2 = t2.open().advance(&1).unwrap();
+        assert_eq!(
+            c.intersect_with([c2])
+                .map(|(k, _, _)| *k)
+                .collect::<Vec<_>>(),
+            vec![5]
+        );
+
+        let c = c.advance(&2).unwrap();
+        let mut keys: Vec<_> = c.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, vec![3, 4]);
+        assert!(c.advance(&5).is_none());
+        assert_eq!(c.advance(&3).unwrap().keys().count(), 0);
+    }
+
+    #[test]
+    fn shared() {
+        testing::shared::<Trie<i32, 2>>(&Bump::new());
+    }
+}
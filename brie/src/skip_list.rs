@@ -1,59 +1,292 @@
+//! A sorted list of tuples, with skip pointers over runs of equal prefixes.
+//!
+//! Tuples are stored flat and in order. For every level `l`, `skips[l][i]`
+//! is the index just past the run of tuples starting at `i` which share
+//! their first `l + 1` values, so walking the keys of a node jumps from run
+//! to run instead of visiting every tuple.
+
 use std::mem;
 
 use bumpalo::Bump;
+use itertools::Itertools;
 
-use crate::sorted::vec::BumpVec;
+use crate::{
+    sorted::vec::{BumpVec, CollectionAllocError},
+    stats::{Stats, TrieStats},
+    store::Storable,
+    Cursor as _, IntoIterType, Oneshot, TryOneshot,
+};
 
-struct SkipList<'bump, T, const N: usize> {
+pub struct SkipList<'bump, T, const N: usize> {
     skips: [BumpVec<'bump, usize>; N],
-    data: BumpVec<'bump, T>,
+    data: BumpVec<'bump, [T; N]>,
 }
 
 impl<'bump, T, const N: usize> SkipList<'bump, T, N>
 where
     T: Clone + Ord + PartialEq,
 {
-    pub fn from_sorted<I>(mut iter: I, bump: &'bump Bump) -> Self
+    pub fn from_sorted<I>(iter: I, bump: &'bump Bump) -> Self
     where
         I: ExactSizeIterator<Item = [T; N]>,
     {
-        assert!(iter.len() >= 1);
+        let len = iter.len();
+        let data = BumpVec::from_iter(iter, bump);
+        let skips = std::array::from_fn(|_| {
+            let mut v = BumpVec::with_capacity_in(len, bump);
+            v.resize(len, 0, bump);
+            v
+        });
 
-        let mut data = BumpVec::with_capacity_in(iter.len(), bump);
-        let mut skips = unsafe {
-            let mut arr: [BumpVec<'bump, usize>; N] = mem::MaybeUninit::uninit().assume_init();
-            for item in &mut arr[..] {
-                std::ptr::write(item, BumpVec::with_capacity_in(iter.len(), bump));
-            }
-            arr
-        };
+        Self::with_skips(skips, data)
+    }
+
+    /// Like [`SkipList::from_sorted`], but fails instead of panicking if the
+    /// arena can't hold the tuples and their skips.
+    pub fn try_from_sorted<I>(iter: I, bump: &'bump Bump) -> Result<Self, CollectionAllocError>
+    where
+        I: ExactSizeIterator<Item = [T; N]>,
+    {
+        let len = iter.len();
+        let data = BumpVec::try_from_iter(iter, bump)?;
+        let mut skips: [BumpVec<'bump, usize>; N] = std::array::from_fn(|_| BumpVec::new());
+        for skip in skips.iter_mut() {
+            // Once reserved, resizing won't allocate
+            skip.try_reserve_exact(len, bump)?;
+            skip.resize(len, 0, bump);
+        }
+
+        Ok(Self::with_skips(skips, data))
+    }
 
-        let prev: [T; N] = iter.next().unwrap();
-        let mut cur_skips: [usize; N] = [1; N];
+    /// Fills in `skips`, which must be as long as `data`.
+    fn with_skips(mut skips: [BumpVec<'bump, usize>; N], data: BumpVec<'bump, [T; N]>) -> Self {
+        let len = data.len();
 
-        for tup in iter {
-            for (i, v) in tup.iter().enumerate() {
-                if &prev[i] == v {
-                    cur_skips[i] += 1;
+        // Walk backwards, so that each run end is known by the time we
+        // reach the start of the run.
+        for (l, skip) in skips.iter_mut().enumerate() {
+            for i in (0..len).rev() {
+                skip[i] = if i + 1 < len && data[i + 1][..=l] == data[i][..=l] {
+                    skip[i + 1]
                 } else {
-                    // Don't increment.
-                    // Push this val into cur skip level
-                    skips[i].push(cur_skips[i], bump);
-                    cur_skips[i] = 0;
-
-                    // For all next levels, push 0
-                    for l in i + 1..N {
-                        skips[l].push(0, bump);
-                        cur_skips[l] = 0;
-                    }
-                }
+                    i + 1
+                };
             }
         }
 
-        for i in 0..N {
-            skips[i].push(0, bump);
+        Self { skips, data }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+impl<'bump, T, const N: usize> Oneshot<'bump, N> for SkipList<'bump, T, N>
+where
+    T: Clone + Ord + 'bump,
+{
+    type Value = T;
+    type IVal = &'bump T;
+    type KeyIter<const M: usize>
+        = iter_type!('bump, &'bump T)
+    where
+        Self: 'bump;
+    type Cursor<'a>
+        = Cursor<'a, 'bump, T, N>
+    where
+        Self: 'a;
+
+    fn from_iter<I: IntoIterator<Item = [Self::Value; N]>>(iter: I, bump: &'bump Bump) -> Self {
+        let tuples = iter.into_iter().sorted().dedup().collect::<Vec<_>>();
+        Self::from_sorted(tuples.into_iter(), bump)
+    }
+
+    fn open(&self) -> Self::Cursor<'_> {
+        Cursor {
+            list: self,
+            start: 0,
+            end: self.data.len(),
+            depth: 0,
         }
+    }
 
-        Self { skips, data }
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
+        self.open()
+            .intersect_with(others.map(|t| t.open()))
+            .map(|(k, _, _)| k)
+            .into_iter_type()
+    }
+}
+
+impl<'bump, T, const N: usize> TryOneshot<'bump, N> for SkipList<'bump, T, N>
+where
+    T: Clone + Ord + 'bump,
+{
+    fn try_from_iter<I: IntoIterator<Item = [T; N]>>(
+        iter: I,
+        bump: &'bump Bump,
+    ) -> Result<Self, CollectionAllocError> {
+        let tuples = iter.into_iter().sorted().dedup().collect::<Vec<_>>();
+        Self::try_from_sorted(tuples.into_iter(), bump)
+    }
+}
+
+/// A [`crate::Cursor`] into a [`SkipList`]: the run of tuples in
+/// `start..end`, which share their first `depth` values.
+pub struct Cursor<'a, 'bump, T, const N: usize> {
+    list: &'a SkipList<'bump, T, N>,
+    start: usize,
+    end: usize,
+    depth: usize,
+}
+
+impl<'a, 'bump, T, const N: usize> Clone for Cursor<'a, 'bump, T, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, 'bump, T, const N: usize> Copy for Cursor<'a, 'bump, T, N> {}
+
+impl<'a, 'bump: 'a, T, const N: usize> Cursor<'a, 'bump, T, N> {
+    /// The start of every run in this cursor, one per key.
+    fn runs(&self) -> impl Iterator<Item = usize> + 'a {
+        let (list, depth, end) = (self.list, self.depth, self.end);
+        // Leaves have no keys
+        let mut i = if depth < N { self.start } else { end };
+
+        std::iter::from_fn(move || {
+            if i < end {
+                let run = i;
+                i = list.skips[depth][i];
+                Some(run)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The cursor for the run starting at `start`.
+    fn child(&self, start: usize) -> Self {
+        Self {
+            start,
+            end: self.list.skips[self.depth][start],
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+}
+
+impl<'a, 'bump: 'a, T: Ord + 'bump, const N: usize> crate::Cursor<'a> for Cursor<'a, 'bump, T, N> {
+    type Value = T;
+    type Keys = iter_type!('a, &'a T);
+    type Intersect<const M: usize> = iter_type!('a, (&'a T, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn len(&self) -> usize {
+        self.runs().count()
+    }
+
+    /// The tuples in this cursor's run, each of which starts at most one
+    /// run of the next column.
+    #[inline]
+    fn len_hint(&self) -> usize {
+        if self.depth < N {
+            self.end - self.start
+        } else {
+            0
+        }
+    }
+
+    fn keys(&self) -> Self::Keys {
+        let (list, depth) = (self.list, self.depth);
+        self.runs()
+            .map(move |i| &list.data[i][depth])
+            .into_iter_type()
+    }
+
+    fn advance(&self, v: &T) -> Option<Self> {
+        let (list, depth) = (self.list, self.depth);
+        let start = self
+            .runs()
+            .take_while(|&i| &list.data[i][depth] <= v)
+            .find(|&i| &list.data[i][depth] == v)?;
+
+        Some(self.child(start))
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        let children = |c: Self| {
+            let (list, depth) = (c.list, c.depth);
+            c.runs().map(move |i| (&list.data[i][depth], c.child(i)))
+        };
+        crate::sorted::intersect_sorted(children(*self), others.map(children)).into_iter_type()
+    }
+}
+
+impl<T, const N: usize> Storable for SkipList<'static, T, N> {
+    type Trie<'bump> = SkipList<'bump, T, N>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'bump, T: Clone + Ord + 'bump, const N: usize> Stats for SkipList<'bump, T, N> {
+    fn stats(&self) -> TrieStats {
+        let skips: usize = self.skips.iter().map(|s| s.capacity()).sum();
+        TrieStats {
+            bytes: self.data.capacity() * mem::size_of::<[T; N]>()
+                + skips * mem::size_of::<usize>(),
+            ..TrieStats::walk(self.open(), |_| (0, 0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+
+    use crate::{Cursor, Oneshot};
+
+    use super::SkipList;
+
+    #[test]
+    fn skips() {
+        let a = Bump::new();
+        let tuples = [[0, 0], [0, 1], [0, 1], [1, 1], [2, 0], [2, 3]];
+        let l = SkipList::from_sorted(tuples.into_iter(), &a);
+
+        assert_eq!(&*l.skips[0], &[3, 3, 3, 4, 6, 6]);
+        assert_eq!(&*l.skips[1], &[1, 3, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn cursor() {
+        let a = Bump::new();
+        let l: SkipList<_, 2> = Oneshot::from_iter([[2, 3], [0, 1], [2, 0], [1, 1], [0, 0]], &a);
+        let l2: SkipList<_, 2> = Oneshot::from_iter([[2, 2], [3, 0], [0, 5]], &a);
+
+        let root = l.open();
+        assert_eq!(root.keys().copied().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(l.intersect([&l2]).copied().collect::<Vec<_>>(), vec![0, 2]);
+
+        let c = root.advance(&2).unwrap();
+        assert_eq!(c.keys().copied().collect::<Vec<_>>(), vec![0, 3]);
+        assert_eq!((root.len(), c.len()), (3, 2));
+        assert!(c.advance(&1).is_none());
+        assert_eq!(c.advance(&3).unwrap().keys().count(), 0);
     }
 }
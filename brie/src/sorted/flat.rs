@@ -1,13 +1,17 @@
 //! A flat list of tuples, backed by a RawVec
 //! Adapted from https://github.com/frankmcsherry/blog/blob/master/posts/2018-05-19.md
 
-use std::marker::PhantomData;
+use std::{marker::PhantomData, mem};
 
 use bumpalo::Bump;
 
-use crate::Oneshot;
+use crate::{
+    stats::{Stats, TrieStats},
+    store::Storable,
+    Cursor as _, IntoIterType, Oneshot, TryOneshot,
+};
 
-use super::vec::BumpVec;
+use super::vec::{BumpVec, CollectionAllocError};
 
 pub struct Read;
 pub struct Write;
@@ -32,6 +36,149 @@ where
     }
 }
 
+impl<'bump, V, const N: usize> Oneshot<'bump, N> for Trie<'bump, V, N, Read>
+where
+    V: Ord + 'bump,
+{
+    type Value = V;
+    type IVal = &'bump V;
+    type KeyIter<const M: usize>
+        = iter_type!('bump, &'bump V)
+    where
+        Self: 'bump;
+    type Cursor<'a>
+        = Cursor<'a, V, N>
+    where
+        Self: 'a;
+
+    fn from_iter<I: IntoIterator<Item = [Self::Value; N]>>(iter: I, bump: &'bump Bump) -> Self {
+        Trie {
+            vec: BumpVec::from_iter(iter, bump),
+            _rw: PhantomData,
+        }
+        .finalize()
+    }
+
+    fn open(&self) -> Cursor<'_, V, N> {
+        Cursor {
+            tuples: &self.vec,
+            depth: 0,
+        }
+    }
+
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
+        self.open()
+            .intersect_with(others.map(|t| t.open()))
+            .map(|(k, _, _)| k)
+            .into_iter_type()
+    }
+}
+
+impl<'bump, V, const N: usize> TryOneshot<'bump, N> for Trie<'bump, V, N, Read>
+where
+    V: Ord + 'bump,
+{
+    fn try_from_iter<I: IntoIterator<Item = [V; N]>>(
+        iter: I,
+        bump: &'bump Bump,
+    ) -> Result<Self, CollectionAllocError> {
+        Ok(Trie {
+            vec: BumpVec::try_from_iter(iter, bump)?,
+            _rw: PhantomData,
+        }
+        .finalize())
+    }
+}
+
+/// A [`crate::Cursor`] into a flat [`Trie`]: the run of tuples which share
+/// their first `depth` values.
+pub struct Cursor<'a, V, const N: usize> {
+    tuples: &'a [[V; N]],
+    depth: usize,
+}
+
+impl<'a, V, const N: usize> Clone for Cursor<'a, V, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, V, const N: usize> Copy for Cursor<'a, V, N> {}
+
+impl<'a, V: Ord + 'a, const N: usize> Cursor<'a, V, N> {
+    /// Each key, along with the cursor for its run of tuples.
+    fn children(self) -> impl Iterator<Item = (&'a V, Self)> {
+        let depth = self.depth;
+        // Leaves have no keys
+        let mut rest = if depth < N { self.tuples } else { &[] };
+
+        std::iter::from_fn(move || {
+            let k = &rest.first()?[depth];
+            let (run, tail) = rest.split_at(rest.partition_point(|t| &t[depth] == k));
+            rest = tail;
+            Some((
+                k,
+                Self {
+                    tuples: run,
+                    depth: depth + 1,
+                },
+            ))
+        })
+    }
+}
+
+impl<'a, V: Ord + 'a, const N: usize> crate::Cursor<'a> for Cursor<'a, V, N> {
+    type Value = V;
+    type Keys = iter_type!('a, &'a V);
+    type Intersect<const M: usize> = iter_type!('a, (&'a V, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn len(&self) -> usize {
+        self.children().count()
+    }
+
+    /// The tuples in this cursor's run, each of which has at most one key.
+    #[inline]
+    fn len_hint(&self) -> usize {
+        if self.depth < N {
+            self.tuples.len()
+        } else {
+            0
+        }
+    }
+
+    fn keys(&self) -> Self::Keys {
+        self.children().map(|(k, _)| k).into_iter_type()
+    }
+
+    fn advance(&self, v: &V) -> Option<Self> {
+        let d = self.depth;
+        if d == N {
+            return None;
+        }
+
+        // The run is sorted on this column, so find where v starts and ends
+        let start = self.tuples.partition_point(|t| &t[d] < v);
+        let end = start + self.tuples[start..].partition_point(|t| &t[d] == v);
+        if start == end {
+            None
+        } else {
+            Some(Self {
+                tuples: &self.tuples[start..end],
+                depth: d + 1,
+            })
+        }
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        super::intersect_sorted(self.children(), others.map(Self::children)).into_iter_type()
+    }
+}
+
 // impl<'bump, V, const N: usize> Oneshot<'bump, N> for Trie<'bump, V, N, Write>
 // where
 //     V: Ord + 'bump,
@@ -122,3 +269,48 @@ where
 //         }
 //     }
 // }
+
+impl<V: Ord, const N: usize, M> Storable for Trie<'static, V, N, M> {
+    type Trie<'bump> = Trie<'bump, V, N, M>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'bump, V: Ord + 'bump, const N: usize> Stats for Trie<'bump, V, N, Read> {
+    fn stats(&self) -> TrieStats {
+        TrieStats {
+            bytes: self.vec.capacity() * mem::size_of::<[V; N]>(),
+            ..TrieStats::walk(self.open(), |_| (0, 0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+
+    use crate::{Cursor, Oneshot};
+
+    use super::{Read, Trie};
+
+    #[test]
+    fn cursor() {
+        let a = Bump::new();
+        let t: Trie<_, 3, Read> =
+            Oneshot::from_iter([[1, 2, 3], [0, 1, 1], [1, 2, 4], [1, 5, 0]], &a);
+        let t2: Trie<_, 3, Read> = Oneshot::from_iter([[1, 5, 1], [2, 2, 2]], &a);
+
+        let root = t.open();
+        assert_eq!(root.keys().copied().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(t.intersect([&t2]).copied().collect::<Vec<_>>(), vec![1]);
+
+        let c = root.advance(&1).unwrap();
+        assert_eq!(c.keys().copied().collect::<Vec<_>>(), vec![2, 5]);
+        let c = c.advance(&2).unwrap();
+        assert_eq!(c.keys().copied().collect::<Vec<_>>(), vec![3, 4]);
+        assert!(c.advance(&5).is_none());
+        assert_eq!(c.advance(&4).unwrap().keys().count(), 0);
+    }
+}
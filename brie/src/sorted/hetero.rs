@@ -0,0 +1,213 @@
+//! Sorted tries with a different key type at every level.
+//!
+//! A relation like `(u32, String, i64)` is stored as
+//! `Level<u32, Level<String, Level<i64, ()>>>`: each level is a sorted
+//! [`Map`] from that column's type to the next level, and `()` marks the
+//! leaves. Use [`Columns`] to go from a flat tuple type to its trie, e.g.
+//! `Trie<'bump, (u32, String, i64)>`.
+//!
+//! Levels can only be intersected with levels of the same key type, which
+//! the types enforce; the rest of the participants' columns may differ.
+
+use std::fmt;
+
+use bumpalo::Bump;
+use itertools::Itertools;
+
+use super::Map;
+
+/// One level of a heterogeneous trie, keyed by `K`.
+pub struct Level<'bump, K, Next>(Map<'bump, K, Next>);
+
+/// A level of a heterogeneous trie, or `()` for the leaves.
+pub trait Node<'bump>: Sized {
+    /// The columns below this node, as a nested tuple `(A, (B, (C, ())))`.
+    type Tuple;
+
+    fn new() -> Self;
+    fn insert(&mut self, tuple: Self::Tuple, bump: &'bump Bump);
+}
+
+impl<'bump> Node<'bump> for () {
+    type Tuple = ();
+
+    #[inline]
+    fn new() -> Self {}
+
+    #[inline]
+    fn insert(&mut self, _tuple: (), _bump: &'bump Bump) {}
+}
+
+impl<'bump, K, Next> Node<'bump> for Level<'bump, K, Next>
+where
+    K: Ord + 'bump,
+    Next: Node<'bump> + 'bump,
+{
+    type Tuple = (K, Next::Tuple);
+
+    #[inline]
+    fn new() -> Self {
+        Self(Map::new())
+    }
+
+    fn insert(&mut self, (k, rest): Self::Tuple, bump: &'bump Bump) {
+        self.0.get_or_insert(k, Next::new, bump).insert(rest, bump);
+    }
+}
+
+impl<'bump, K, Next> Level<'bump, K, Next>
+where
+    K: Ord + 'bump,
+    Next: 'bump,
+{
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.len() == 0
+    }
+
+    #[inline]
+    pub fn advance(&self, k: &K) -> Option<&Next> {
+        self.0.get(k)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.0.iter().map(|(k, _)| k)
+    }
+
+    /// Intersects the keys of this level with levels of other tries which
+    /// share this level's key type.
+    pub fn intersect<O: 'bump, const M: usize>(
+        &'bump self,
+        others: [&'bump Level<'bump, K, O>; M],
+    ) -> impl Iterator<Item = &'bump K> + 'bump {
+        // The levels below differ, so only the keys take part in the merge.
+        let this = self.0.iter().map(|(k, _)| (k, ()));
+        let others = others.map(|t| t.0.iter().map(|(k, _)| (k, ())));
+        super::intersect_sorted(this, others).map(|(k, _, _)| k)
+    }
+}
+
+impl<'bump, K, Next> Default for Level<'bump, K, Next>
+where
+    K: Ord + 'bump,
+    Next: Node<'bump> + 'bump,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'bump, K, Next> fmt::Debug for Level<'bump, K, Next>
+where
+    K: Ord + fmt::Debug + 'bump,
+    Next: fmt::Debug + 'bump,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Flat tuples of columns, e.g. `(u32, String, i64)`, and the trie which
+/// stores them.
+pub trait Columns<'bump>: Sized {
+    type Trie: Node<'bump>;
+
+    /// Converts `(A, B, C)` into the nested `(A, (B, (C, ())))`.
+    fn nest(self) -> <Self::Trie as Node<'bump>>::Tuple;
+}
+
+/// The trie storing tuples of type `T`.
+pub type Trie<'bump, T> = <T as Columns<'bump>>::Trie;
+
+/// Builds the trie for an iterator of flat tuples.
+pub fn from_tuples<'bump, T, I>(iter: I, bump: &'bump Bump) -> Trie<'bump, T>
+where
+    T: Columns<'bump> + Ord,
+    I: IntoIterator<Item = T>,
+{
+    let mut res = <T::Trie as Node>::new();
+    for tuple in iter.into_iter().sorted() {
+        res.insert(tuple.nest(), bump);
+    }
+    res
+}
+
+macro_rules! level_ty {
+    ($b:lifetime;) => { () };
+    ($b:lifetime; $t:ident $(, $rest:ident)*) => { Level<$b, $t, level_ty!($b; $($rest),*)> };
+}
+
+macro_rules! nest_val {
+    () => { () };
+    ($t:ident $(, $rest:ident)*) => { ($t, nest_val!($($rest),*)) };
+}
+
+macro_rules! impl_columns {
+    ($($t:ident),+) => {
+        impl<'bump, $($t: Ord + 'bump),+> Columns<'bump> for ($($t,)+) {
+            type Trie = level_ty!('bump; $($t),+);
+
+            #[inline]
+            #[allow(non_snake_case)]
+            fn nest(self) -> <Self::Trie as Node<'bump>>::Tuple {
+                let ($($t,)+) = self;
+                nest_val!($($t),+)
+            }
+        }
+    };
+}
+
+impl_columns!(A);
+impl_columns!(A, B);
+impl_columns!(A, B, C);
+impl_columns!(A, B, C, D);
+impl_columns!(A, B, C, D, E);
+impl_columns!(A, B, C, D, E, F);
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+
+    use super::{from_tuples, Trie};
+
+    #[test]
+    fn mixed_columns() {
+        let a = Bump::new();
+        let people: Trie<(u32, &str, i64)> = from_tuples(
+            [
+                (2, "bob", 20),
+                (1, "alice", 10),
+                (3, "carol", 30),
+                (1, "alice", 11),
+            ],
+            &a,
+        );
+        let visits: Trie<(u32, bool)> = from_tuples([(1, true), (3, false), (4, true)], &a);
+
+        let ids: Vec<_> = people.intersect([&visits]).copied().collect();
+        assert_eq!(ids, vec![1, 3]);
+
+        let alice = people.advance(&1).unwrap().advance(&"alice").unwrap();
+        assert_eq!(alice.keys().copied().collect::<Vec<_>>(), vec![10, 11]);
+
+        // Below the root, levels intersect with any level of the same key type
+        let pets: Trie<(&str, &str)> =
+            from_tuples([("alice", "cat"), ("bob", "dog"), ("dave", "fish")], &a);
+        let names: Vec<_> = people
+            .advance(&2)
+            .unwrap()
+            .intersect([&pets])
+            .copied()
+            .collect();
+        assert_eq!(names, vec!["bob"]);
+
+        let ages: Trie<(i64,)> = from_tuples([(9,), (11,), (12,)], &a);
+        let ages: Vec<_> = alice.intersect([&ages]).copied().collect();
+        assert_eq!(ages, vec![11]);
+    }
+}
@@ -1,5 +1,52 @@
 pub mod flat;
+pub mod hetero;
 pub mod nested;
+pub mod soa;
 pub mod vec;
+pub mod weighted;
 
 pub use nested::*;
+
+/// Intersects iterators of `(key, child)` pairs, sorted and deduplicated by
+/// key, with a linear merge. Yields each shared key along with its child
+/// from `this` and from each of `others`.
+pub(crate) fn intersect_sorted<'a, K, X, T, I, const M: usize>(
+    mut this: T,
+    others: [I; M],
+) -> impl Iterator<Item = (&'a K, X, [X; M])>
+where
+    K: Ord + 'a,
+    T: Iterator<Item = (&'a K, X)>,
+    I: Iterator<Item = (&'a K, X)>,
+{
+    let mut others = others.map(|it| it.peekable());
+
+    let mut exhausted = false;
+
+    std::iter::from_fn(move || {
+        while !exhausted {
+            let (this_key, this_child) = this.next()?;
+            let children = crate::try_from_fn(|i| {
+                let it = &mut others[i];
+                loop {
+                    let Some((k, _)) = it.peek() else {
+                        exhausted = true;
+                        return None;
+                    };
+                    if *k > this_key {
+                        return None;
+                    } else if *k == this_key {
+                        return it.next().map(|(_, x)| x);
+                    }
+                    it.next();
+                }
+            });
+
+            if let Some(children) = children {
+                return Some((this_key, this_child, children));
+            }
+        }
+        None
+    })
+    .fuse()
+}
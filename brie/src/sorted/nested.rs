@@ -1,20 +1,23 @@
-//! Sorted maps/tries, backed by a RawVec
+//! Sorted maps/tries, backed by a BumpVec
 
 use bumpalo::Bump;
 use itertools::Itertools;
 
-use core::{fmt, ptr, slice};
-use std::{cmp::Ordering, mem::MaybeUninit, ops};
+use core::fmt;
+use std::{cmp::Ordering, mem, ops};
 
-use crate::Oneshot;
+use crate::{
+    on_workers,
+    stats::{Stats, TrieStats},
+    store::Storable,
+    Cursor, DynOneshot, IntoIterType, NodeCursor, Oneshot, TryOneshot,
+};
 
-use super::vec::RawVec;
+use super::vec::{BumpVec, CollectionAllocError};
 
-// TODO: bench SoA approach:
-//       two bufs, one for key, one for val
+// See `soa::Map` for the structure-of-arrays layout of the same map.
 pub struct Map<'bump, K: 'bump, V: 'bump> {
-    buf: RawVec<'bump, (K, V)>,
-    len: usize,
+    vec: BumpVec<'bump, (K, V)>,
 }
 
 impl<'bump, K, V> Map<'bump, K, V>
@@ -25,106 +28,119 @@ where
     #[inline]
     pub fn new() -> Self {
         Self {
-            buf: RawVec::new(),
-            len: 0,
+            vec: BumpVec::new(),
         }
     }
 
     #[inline]
     pub fn with_capacity(cap: usize, bump: &'bump Bump) -> Self {
         Self {
-            buf: RawVec::with_capacity_in(cap, bump),
-            len: 0,
+            vec: BumpVec::with_capacity_in(cap, bump),
         }
     }
 
+    pub fn try_with_capacity(cap: usize, bump: &'bump Bump) -> Result<Self, CollectionAllocError> {
+        Ok(Self {
+            vec: BumpVec::try_with_capacity_in(cap, bump)?,
+        })
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
-        self.len
+        self.vec.len()
     }
 
     #[inline]
-    pub fn push(&mut self, value: (K, V), bump: &'bump Bump) {
-        // This will panic or abort if we would allocate > isize::MAX bytes
-        // or if the length increment would overflow for zero-sized types.
-        if self.len == self.buf.cap() {
-            self.reserve(1, bump);
-        }
-        unsafe {
-            let end = self.buf.ptr().add(self.len);
-            ptr::write(end, value);
-            self.len += 1;
-        }
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
     }
 
-    pub fn reserve(&mut self, additional: usize, bump: &'bump Bump) {
-        self.buf.reserve(self.len, additional, bump);
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.vec.capacity()
     }
 
     #[inline]
-    pub unsafe fn set_len(&mut self, new_len: usize) {
-        self.len = new_len;
+    pub fn push(&mut self, value: (K, V), bump: &'bump Bump) {
+        self.vec.push(value, bump);
     }
 
-    fn write_at_ix(&mut self, index: usize, element: (K, V)) -> (K, V) {
-        let len = self.len();
-        debug_assert!(index <= len);
-
-        unsafe {
-            // infallible
-            // The spot to put the new value
-            let p = self.as_mut_ptr().add(index);
-            let v = ptr::read(p);
-            // Write it in!
-            ptr::write(p, element);
-
-            v
-        }
+    /// Like [`Map::push`], but hands `value` back along with the error if
+    /// there's no room for it.
+    #[inline]
+    pub fn try_push(
+        &mut self,
+        value: (K, V),
+        bump: &'bump Bump,
+    ) -> Result<(), ((K, V), CollectionAllocError)> {
+        self.vec.try_push(value, bump)
     }
 
-    fn insert_at_ix(&mut self, index: usize, element: (K, V), bump: &'bump Bump) -> &mut (K, V) {
-        let len = self.len();
-        debug_assert!(index <= len);
-
-        // space for the new element
-        if len == self.buf.cap() {
-            self.reserve(1, bump);
-        }
-
-        unsafe {
-            // infallible
-            // The spot to put the new value
-            let p = self.as_mut_ptr().add(index);
-            // Shift everything over to make space. (Duplicating the
-            // `index`th element into two consecutive places.)
-            ptr::copy(p, p.offset(1), len - index);
-            // Write it in, overwriting the first copy of the `index`th
-            // element.
-            ptr::write(p, element);
+    pub fn reserve(&mut self, additional: usize, bump: &'bump Bump) {
+        self.vec.reserve(additional, bump);
+    }
 
-            self.set_len(len + 1);
+    pub fn try_reserve(
+        &mut self,
+        additional: usize,
+        bump: &'bump Bump,
+    ) -> Result<(), CollectionAllocError> {
+        self.vec.try_reserve(additional, bump)
+    }
 
-            &mut *p
-        }
+    /// # Safety
+    ///
+    /// As for [`BumpVec::set_len`].
+    #[inline]
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        self.vec.set_len(new_len);
     }
 
     pub fn get(&self, k: &K) -> Option<&V> {
-        self.binary_search_by(|v| k.cmp(v))
+        self.binary_search_by(|v| v.cmp(k))
             .ok()
             // SAFETY: binary_search_by guarantees x < len
             .map(|x| &unsafe { self.get_unchecked(x) }.1)
     }
 
+    pub fn get_mut(&mut self, k: &K) -> Option<&mut V> {
+        self.binary_search_by(|v| v.cmp(k))
+            .ok()
+            // SAFETY: binary_search_by guarantees x < len
+            .map(|x| &mut unsafe { self.get_unchecked_mut(x) }.1)
+    }
+
     pub fn insert(&mut self, k: K, v: V, bump: &'bump Bump) -> Option<V> {
-        match self.binary_search_by(|v| k.cmp(v)) {
-            Ok(found) => Some(self.write_at_ix(found, (k, v)).1),
+        match self.binary_search_by(|x| x.cmp(&k)) {
+            Ok(found) => Some(mem::replace(&mut self.vec[found], (k, v)).1),
             Err(none) => {
-                self.insert_at_ix(none, (k, v), bump);
+                self.vec.insert(none, (k, v), bump);
                 None
             }
         }
     }
 
+    /// Like [`Map::insert`], but hands the new entry back along with the
+    /// error if there's no room for it. Replacing an existing key never
+    /// allocates.
+    pub fn try_insert(
+        &mut self,
+        k: K,
+        v: V,
+        bump: &'bump Bump,
+    ) -> Result<Option<V>, ((K, V), CollectionAllocError)> {
+        match self.binary_search_by(|x| x.cmp(&k)) {
+            Ok(found) => Ok(Some(mem::replace(&mut self.vec[found], (k, v)).1)),
+            Err(none) => self.vec.try_insert(none, (k, v), bump).map(|()| None),
+        }
+    }
+
+    pub fn remove(&mut self, k: &K) -> Option<V> {
+        self.binary_search_by(|v| v.cmp(k))
+            .ok()
+            .map(|found| self.vec.remove(found).1)
+    }
+
     // TODO: maybe actually impl the entry api
     pub fn get_or_insert<F>(&mut self, k: K, mut vf: F, bump: &'bump Bump) -> &mut V
     where
@@ -133,7 +149,32 @@ where
         match self.binary_search_by(|v| v.cmp(&k)) {
             // SAFETY: binary_search_by guarantees found < len
             Ok(found) => unsafe { &mut self.get_unchecked_mut(found).1 },
-            Err(none) => &mut self.insert_at_ix(none, (k, vf()), bump).1,
+            Err(none) => {
+                self.vec.insert(none, (k, vf()), bump);
+                &mut self.vec[none].1
+            }
+        }
+    }
+
+    /// Like [`Map::get_or_insert`], but fails instead of panicking if there's
+    /// no room for a new entry. `vf` isn't called if `k` is already present.
+    pub fn try_get_or_insert<F>(
+        &mut self,
+        k: K,
+        mut vf: F,
+        bump: &'bump Bump,
+    ) -> Result<&mut V, CollectionAllocError>
+    where
+        F: FnMut() -> V,
+    {
+        match self.binary_search_by(|v| v.cmp(&k)) {
+            Ok(found) => Ok(&mut self.vec[found].1),
+            Err(none) => {
+                self.vec
+                    .try_insert(none, (k, vf()), bump)
+                    .map_err(|(_, e)| e)?;
+                Ok(&mut self.vec[none].1)
+            }
         }
     }
 
@@ -162,7 +203,7 @@ where
                 right = mid;
             } else {
                 // SAFETY: same as the `get_unchecked` above
-                unsafe { core::intrinsics::assume(mid < self.len()) };
+                unsafe { std::hint::assert_unchecked(mid < self.len()) };
                 return Ok(mid);
             }
 
@@ -172,25 +213,23 @@ where
     }
 }
 
+impl<'bump, K: 'bump + Ord, V: 'bump> Default for Map<'bump, K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'bump, K: 'bump, V: 'bump> ops::Deref for Map<'bump, K, V> {
     type Target = [(K, V)];
 
     fn deref(&self) -> &[(K, V)] {
-        unsafe {
-            let p = self.buf.ptr();
-            // assume(!p.is_null());
-            slice::from_raw_parts(p, self.len)
-        }
+        &self.vec
     }
 }
 
 impl<'bump, K: 'bump, V: 'bump> ops::DerefMut for Map<'bump, K, V> {
     fn deref_mut(&mut self) -> &mut [(K, V)] {
-        unsafe {
-            let ptr = self.buf.ptr();
-            // assume(!ptr.is_null());
-            slice::from_raw_parts_mut(ptr, self.len)
-        }
+        &mut self.vec
     }
 }
 
@@ -202,75 +241,318 @@ impl<'bump, K: 'bump + fmt::Debug, V: 'bump + fmt::Debug> fmt::Debug for Map<'bu
 
 pub struct Trie<'a, T>(Map<'a, T, Self>);
 
+/// How many first values [`Trie::par_from_iter`] samples per worker to pick
+/// the range of first values each worker builds.
+const SAMPLES_PER_WORKER: usize = 16;
+
+impl<'bump, V> Trie<'bump, V>
+where
+    V: Ord + Clone + 'bump,
+{
+    #[inline]
+    pub fn new() -> Self {
+        Self(Map::new())
+    }
+
+    /// Inserts a tuple of any length, one level per value.
+    pub fn insert<I: IntoIterator<Item = V>>(&mut self, tuple: I, bump: &'bump Bump) {
+        let mut trie = self;
+        for v in tuple {
+            trie = trie.0.get_or_insert(v, Self::new, bump);
+        }
+    }
+
+    /// Like [`Trie::insert`], but fails instead of panicking once the arena
+    /// runs out of room. Any levels inserted before then stay in the trie.
+    pub fn try_insert<I: IntoIterator<Item = V>>(
+        &mut self,
+        tuple: I,
+        bump: &'bump Bump,
+    ) -> Result<(), CollectionAllocError> {
+        let mut trie = self;
+        for v in tuple {
+            trie = trie.0.try_get_or_insert(v, Self::new, bump)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn advance(&self, v: &V) -> Option<&Self> {
+        self.0.get(v)
+    }
+
+    /// Intersects the keys at the top level of `self` and `others`.
+    pub fn intersect_keys<'a, const M: usize>(
+        &'a self,
+        others: [&'a Self; M],
+    ) -> impl Iterator<Item = &'a V> + 'a {
+        // To do intersection, we do a linear pass through all tries.
+        let children = |t: &'a Self| t.0.iter().map(|(k, child)| (k, child));
+        super::intersect_sorted(children(self), others.map(children)).map(|(k, _, _)| k)
+    }
+}
+
+impl<'bump, V> Trie<'bump, V>
+where
+    V: Ord + Clone + Send + 'bump,
+{
+    /// Builds the same trie as [`Oneshot::from_iter`], with one worker per
+    /// arena in `arenas`; the calling thread is the first.
+    ///
+    /// Tuples are split between the workers by their first value, in ranges
+    /// picked from a sample of the first values, so every child of the root
+    /// is built whole by one worker: each sorts its share and inserts it into
+    /// a trie in its own arena. The root is then stitched together from
+    /// theirs in the first arena, so the trie borrows every arena.
+    ///
+    /// Panics if `arenas` is empty. A panic while building a share, say from
+    /// `V`'s [`Ord`] impl, is passed on after the other shares are built.
+    pub fn par_from_iter<I, const N: usize>(iter: I, arenas: &'bump mut [Bump]) -> Self
+    where
+        I: IntoIterator<Item = [V; N]>,
+    {
+        let tuples = iter.into_iter().collect::<Vec<_>>();
+        let shares = match N {
+            0 => vec![tuples],
+            _ => partition(tuples, arenas.len()),
+        };
+        let (first, parts) =
+            on_workers(arenas, shares, |arena, share| Self::from_vec(share, arena));
+
+        // Each part has a disjoint range of first values, in order
+        let len = parts.iter().map(|t| t.0.len()).sum();
+        let mut res = Self(Map::with_capacity(len, first));
+        for part in parts {
+            for child in part.0.vec {
+                res.0.push(child, first);
+            }
+        }
+        res
+    }
+
+    fn from_vec<const N: usize>(mut tuples: Vec<[V; N]>, bump: &'bump Bump) -> Self {
+        tuples.sort_unstable();
+
+        let mut res = Self::new();
+        for tuple in tuples {
+            res.insert(tuple, bump);
+        }
+        res
+    }
+}
+
+/// Splits `tuples` into `parts` shares by their first value: every tuple in
+/// one share has a smaller first value than every tuple in the next.
+fn partition<V: Ord + Clone, const N: usize>(
+    tuples: Vec<[V; N]>,
+    parts: usize,
+) -> Vec<Vec<[V; N]>> {
+    // Nothing to sample, and nothing to share out
+    if tuples.is_empty() {
+        return vec![tuples];
+    }
+
+    let step = (tuples.len() / (parts * SAMPLES_PER_WORKER)).max(1);
+    let mut sample = tuples
+        .iter()
+        .step_by(step)
+        .map(|t| t[0].clone())
+        .collect::<Vec<_>>();
+    sample.sort_unstable();
+
+    // Share `i` gets the first values from `splitters[i - 1]` up to
+    // `splitters[i]`; repeated splitters just leave some shares empty
+    let splitters = (1..parts)
+        .map(|i| sample[i * sample.len() / parts].clone())
+        .collect::<Vec<_>>();
+    let mut shares = vec![Vec::new(); parts];
+    for t in tuples {
+        shares[splitters.partition_point(|s| *s <= t[0])].push(t);
+    }
+    shares
+}
+
+impl<'bump, V: PartialEq> PartialEq for Trie<'bump, V> {
+    fn eq(&self, other: &Self) -> bool {
+        *self.0 == *other.0
+    }
+}
+
+impl<'bump, V: Eq> Eq for Trie<'bump, V> {}
+
+impl<'bump, V: fmt::Debug> fmt::Debug for Trie<'bump, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl<'bump, V> Default for Trie<'bump, V>
+where
+    V: Ord + Clone + 'bump,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'bump, V, const N: usize> Oneshot<'bump, N> for Trie<'bump, V>
 where
     V: Ord + Clone + std::fmt::Debug + 'bump,
 {
     type Value = V;
     type IVal = &'bump V;
-    type KeyIter<const M: usize> = impl Iterator<Item = &'bump Self::Value>
-    where Self: 'bump;
+    type KeyIter<const M: usize>
+        = iter_type!('bump, &'bump Self::Value)
+    where
+        Self: 'bump;
+    type Cursor<'a>
+        = NodeCursor<'a, Self>
+    where
+        Self: 'a;
 
     fn from_iter<I: IntoIterator<Item = [Self::Value; N]>>(iter: I, bump: &'bump Bump) -> Self {
-        let mut res = Self(Map::new());
+        TryOneshot::try_from_iter(iter, bump).unwrap_or_else(|e| e.raise())
+    }
+
+    fn open(&self) -> NodeCursor<'_, Self> {
+        NodeCursor::new(self)
+    }
+
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
+        self.intersect_keys(others).into_iter_type()
+    }
+}
+
+impl<'bump, V, const N: usize> TryOneshot<'bump, N> for Trie<'bump, V>
+where
+    V: Ord + Clone + std::fmt::Debug + 'bump,
+{
+    fn try_from_iter<I: IntoIterator<Item = [V; N]>>(
+        iter: I,
+        bump: &'bump Bump,
+    ) -> Result<Self, CollectionAllocError> {
+        let mut res = Self::new();
 
         for tuple in iter.into_iter().sorted() {
-            let mut trie = &mut res;
-            for v in tuple {
-                trie = trie.0.get_or_insert(v.clone(), || Self(Map::new()), bump);
-            }
+            res.try_insert(tuple, bump)?;
         }
 
-        res
+        Ok(res)
     }
+}
 
-    fn advance(&'bump self, v: &Self::Value) -> Option<&'bump Self> {
-        self.0.get(v)
+impl<'a, 'bump: 'a, V> Cursor<'a> for NodeCursor<'a, Trie<'bump, V>>
+where
+    V: Ord + Clone + 'bump,
+{
+    type Value = V;
+    type Keys = iter_type!('a, &'a V);
+    type Intersect<const M: usize> = iter_type!('a, (&'a V, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
     }
 
-    fn intersect<'a, const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
-        // To do intersection, we do a linear pass through all tries.
-        let mut this_iter = self.0.iter();
+    #[inline]
+    fn len(&self) -> usize {
+        self.node.0.len()
+    }
 
-        let mut others: [std::iter::Peekable<_>; M] = unsafe {
-            let mut arr: [_; M] = MaybeUninit::uninit().assume_init();
-            for (item, t) in (&mut arr[..]).into_iter().zip(others) {
-                std::ptr::write(item, t.0.iter().peekable());
-            }
-            arr
-        };
+    fn keys(&self) -> Self::Keys {
+        self.node.0.iter().map(|(k, _)| k).into_iter_type()
+    }
 
-        std::iter::from_fn(move || {
-            'outer: loop {
-                // If we still have a value here
-                if let Some((this_val, _)) = this_iter.next() {
-                    // For each of our other values
-                    'next: for it in others.iter_mut() {
-                        'inner: loop {
-                            if let Some((k, _)) = it.peek() {
-                                if k > this_val {
-                                    continue 'outer;
-                                } else if k == this_val {
-                                    it.next();
-                                    continue 'next;
-                                } else {
-                                    it.next();
-                                    continue 'inner;
-                                }
-                            } else {
-                                return None;
-                            }
-                        }
-                    }
-
-                    // Passed!
-                    return Some(this_val);
-                } else {
-                    return None;
-                }
-            }
-        })
-        .fuse()
+    fn advance(&self, v: &V) -> Option<Self> {
+        self.node.0.get(v).map(|t| self.child(t))
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        let children = |c: Self| c.node.0.iter().map(move |(k, t)| (k, c.child(t)));
+        super::intersect_sorted(children(*self), others.map(children)).into_iter_type()
+    }
+}
+
+/// A [`Trie`] whose arity is only known at runtime.
+///
+/// Derefs to the underlying [`Trie`], which navigates the same either way.
+pub struct DynTrie<'bump, V> {
+    arity: usize,
+    trie: Trie<'bump, V>,
+}
+
+impl<'bump, V> ops::Deref for DynTrie<'bump, V> {
+    type Target = Trie<'bump, V>;
+
+    fn deref(&self) -> &Trie<'bump, V> {
+        &self.trie
+    }
+}
+
+impl<'bump, V> DynTrie<'bump, V>
+where
+    V: Ord + Clone + 'bump,
+{
+    /// The fallible counterpart of [`DynOneshot::from_iter`].
+    pub fn try_from_iter<I, R>(
+        arity: usize,
+        iter: I,
+        bump: &'bump Bump,
+    ) -> Result<Self, CollectionAllocError>
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[V]>,
+    {
+        let mut tuples = iter.into_iter().collect::<Vec<_>>();
+        tuples.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+
+        let mut trie = Trie::new();
+        for tuple in tuples {
+            let tuple = tuple.as_ref();
+            assert_eq!(tuple.len(), arity, "tuple has the wrong arity");
+            trie.try_insert(tuple.iter().cloned(), bump)?;
+        }
+
+        Ok(Self { arity, trie })
+    }
+}
+
+impl<'bump, V> DynOneshot<'bump> for DynTrie<'bump, V>
+where
+    V: Ord + Clone + std::fmt::Debug + 'bump,
+{
+    type Value = V;
+    type IVal = &'bump V;
+    type KeyIter<const M: usize>
+        = iter_type!('bump, &'bump Self::Value)
+    where
+        Self: 'bump;
+    type Cursor<'a>
+        = NodeCursor<'a, Trie<'bump, V>>
+    where
+        Self: 'a;
+
+    fn from_iter<I, R>(arity: usize, iter: I, bump: &'bump Bump) -> Self
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[V]>,
+    {
+        Self::try_from_iter(arity, iter, bump).unwrap_or_else(|e| e.raise())
+    }
+
+    #[inline]
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn open(&self) -> NodeCursor<'_, Trie<'bump, V>> {
+        NodeCursor::new(&self.trie)
+    }
+
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
+        self.trie
+            .intersect_keys(others.map(|o| &o.trie))
+            .into_iter_type()
     }
 }
 
@@ -304,13 +586,50 @@ where
 //     }
 // }
 
+impl<V> Storable for Trie<'static, V> {
+    type Trie<'bump> = Trie<'bump, V>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'bump, V> Stats for Trie<'bump, V>
+where
+    V: Ord + Clone + 'bump,
+{
+    fn stats(&self) -> TrieStats {
+        TrieStats::walk(NodeCursor::new(self), |c| {
+            let cap = c.node.0.capacity();
+            (cap, cap * mem::size_of::<(V, Self)>())
+        })
+    }
+}
+
+impl<V> Storable for DynTrie<'static, V> {
+    type Trie<'bump> = DynTrie<'bump, V>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'bump, V> Stats for DynTrie<'bump, V>
+where
+    V: Ord + Clone + 'bump,
+{
+    fn stats(&self) -> TrieStats {
+        self.trie.stats()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use bumpalo::Bump;
 
-    use crate::{sorted::nested, Oneshot};
+    use crate::{sorted::nested, Cursor, DynOneshot, Oneshot};
 
-    use super::Trie;
+    use super::{DynTrie, Trie};
 
     #[test]
     fn iter_keys() {
@@ -321,4 +640,108 @@ mod test {
         let v: usize = <nested::Trie<'_, i32> as Oneshot<1>>::intersect(&t, [&t2]).count();
         assert_eq!(v, 5);
     }
+
+    #[test]
+    fn try_insert() {
+        let a = Bump::new();
+        let mut m = nested::Map::new();
+        assert_eq!(m.try_insert(1, 'a', &a), Ok(None));
+        assert_eq!(m.try_insert(1, 'b', &a), Ok(Some('a')));
+
+        // Replacing a value needs no room in the arena
+        a.set_allocation_limit(Some(a.allocated_bytes()));
+        assert_eq!(m.try_insert(1, 'c', &a), Ok(Some('b')));
+    }
+
+    #[test]
+    fn dyn_arity() {
+        let a = Bump::new();
+        let t: DynTrie<i32> = DynOneshot::from_iter(3, (0..10).map(|x| vec![x, 1, x]), &a);
+        let t2: DynTrie<i32> = DynOneshot::from_iter(3, [[2, 0, 0], [4, 1, 1]], &a);
+
+        assert_eq!(t.arity(), 3);
+        let v: Vec<_> = t.intersect([&t2]).copied().collect();
+        assert_eq!(v, vec![2, 4]);
+        assert!(t
+            .advance(&4)
+            .unwrap()
+            .advance(&1)
+            .unwrap()
+            .advance(&4)
+            .is_some());
+    }
+
+    #[test]
+    fn cursor() {
+        let a = Bump::new();
+        let t: Trie<i32> = Oneshot::<2>::from_iter([[1, 2], [1, 3], [2, 2]], &a);
+        let t2: Trie<i32> = Oneshot::<2>::from_iter([[1, 3], [1, 4]], &a);
+
+        let root = Oneshot::<2>::open(&t);
+        assert_eq!(root.keys().copied().collect::<Vec<_>>(), vec![1, 2]);
+
+        let c = root.advance(&1).unwrap();
+        let c2 = Oneshot::<2>::open(&t2).advance(&1).unwrap();
+        assert_eq!(c.depth(), 1);
+        assert_eq!((root.len(), c.len()), (2, 2));
+        assert_eq!(
+            c.intersect_with([c2])
+                .map(|(k, _, _)| *k)
+                .collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert!(c.advance(&3).unwrap().keys().next().is_none());
+        assert!(root.advance(&3).is_none());
+
+        // Children come back already descended into
+        let r2 = Oneshot::<2>::open(&t2);
+        let (k, child, [child2]) = root.intersect_with([r2]).next().unwrap();
+        assert_eq!(*k, 1);
+        assert_eq!((child.depth(), child2.depth()), (1, 1));
+        assert_eq!(child.keys().copied().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(child2.keys().copied().collect::<Vec<_>>(), vec![3, 4]);
+    }
+
+    #[test]
+    fn parallel() {
+        // Scrambled, so each worker's share arrives out of order
+        let tuples = (0..6 * 6 * 6 * 6 * 6)
+            .map(|i: i32| (i * 4219) % 7776)
+            .map(|i| [i % 6, i / 6 % 6, i / 36 % 6, i / 216 % 6, i / 1296])
+            .collect::<Vec<_>>();
+
+        let a = Bump::new();
+        let seq: Trie<i32> = Oneshot::<5>::from_iter(tuples.iter().cloned(), &a);
+
+        for workers in [1, 2, 4, 8] {
+            let mut arenas = (0..workers).map(|_| Bump::new()).collect::<Vec<_>>();
+            let par = Trie::par_from_iter(tuples.iter().cloned(), &mut arenas);
+            assert_eq!(par, seq);
+        }
+
+        // Skewed and tiny inputs leave some workers nothing to do
+        let mut arenas = (0..4).map(|_| Bump::new()).collect::<Vec<_>>();
+        let skewed = (0..100).map(|x| [1, x]);
+        let seq: Trie<i32> = Oneshot::<2>::from_iter(skewed.clone(), &a);
+        assert_eq!(Trie::par_from_iter(skewed, &mut arenas), seq);
+
+        let mut arenas = (0..4).map(|_| Bump::new()).collect::<Vec<_>>();
+        let seq: Trie<i32> = Oneshot::<2>::from_iter([[3, 1], [2, 2]], &a);
+        assert_eq!(Trie::par_from_iter([[3, 1], [2, 2]], &mut arenas), seq);
+
+        let mut arenas = (0..4).map(|_| Bump::new()).collect::<Vec<_>>();
+        let seq: Trie<i32> = Oneshot::<2>::from_iter([[5, 6]], &a);
+        assert_eq!(Trie::par_from_iter([[5, 6]], &mut arenas), seq);
+    }
+
+    #[test]
+    fn parallel_empty() {
+        let a = Bump::new();
+        let seq: Trie<i32> = Oneshot::<3>::from_iter([], &a);
+
+        let mut arenas = (0..4).map(|_| Bump::new()).collect::<Vec<_>>();
+        let par = Trie::<i32>::par_from_iter::<_, 3>([], &mut arenas);
+        assert_eq!(par, seq);
+        assert_eq!(Oneshot::<3>::open(&par).len(), 0);
+    }
 }
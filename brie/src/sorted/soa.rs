@@ -0,0 +1,373 @@
+//! Structure-of-arrays sorted maps/tries, backed by two RawVecs
+//!
+//! Same API as [`super::nested`], but keys and values live in separate
+//! buffers, so binary searches and intersection scans only touch keys.
+
+use bumpalo::Bump;
+use itertools::Itertools;
+
+use core::{fmt, ptr, slice};
+use std::{cmp::Ordering, iter, mem};
+
+use crate::{
+    stats::{Stats, TrieStats},
+    store::Storable,
+    Cursor, IntoIterType, NodeCursor, Oneshot,
+};
+
+use super::vec::RawVec;
+
+pub struct Map<'bump, K: 'bump, V: 'bump> {
+    keys: RawVec<'bump, K>,
+    vals: RawVec<'bump, V>,
+    len: usize,
+}
+
+impl<'bump, K, V> Map<'bump, K, V>
+where
+    K: 'bump + Ord + Eq,
+    V: 'bump,
+{
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            keys: RawVec::new(),
+            vals: RawVec::new(),
+            len: 0,
+        }
+    }
+
+    #[inline]
+    pub fn with_capacity(cap: usize, bump: &'bump Bump) -> Self {
+        Self {
+            keys: RawVec::with_capacity_in(cap, bump),
+            vals: RawVec::with_capacity_in(cap, bump),
+            len: 0,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn push(&mut self, value: (K, V), bump: &'bump Bump) {
+        if self.len == self.cap() {
+            self.reserve(1, bump);
+        }
+        unsafe {
+            ptr::write(self.keys.ptr().add(self.len), value.0);
+            ptr::write(self.vals.ptr().add(self.len), value.1);
+            self.len += 1;
+        }
+    }
+
+    pub fn reserve(&mut self, additional: usize, bump: &'bump Bump) {
+        self.keys.reserve(self.len, additional, bump);
+        self.vals.reserve(self.len, additional, bump);
+    }
+
+    #[inline]
+    fn cap(&self) -> usize {
+        // Both buffers grow in lockstep, but may round up differently
+        self.keys.cap().min(self.vals.cap())
+    }
+
+    /// # Safety
+    ///
+    /// `new_len` must fit in both buffers, and the first `new_len` keys and
+    /// values must be initialized.
+    #[inline]
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        self.len = new_len;
+    }
+
+    #[inline]
+    pub fn keys(&self) -> &[K] {
+        unsafe { slice::from_raw_parts(self.keys.ptr(), self.len) }
+    }
+
+    #[inline]
+    pub fn values(&self) -> &[V] {
+        unsafe { slice::from_raw_parts(self.vals.ptr(), self.len) }
+    }
+
+    #[inline]
+    pub fn values_mut(&mut self) -> &mut [V] {
+        unsafe { slice::from_raw_parts_mut(self.vals.ptr(), self.len) }
+    }
+
+    #[inline]
+    pub fn iter(&self) -> iter::Zip<slice::Iter<'_, K>, slice::Iter<'_, V>> {
+        self.keys().iter().zip(self.values().iter())
+    }
+
+    fn insert_at_ix(&mut self, index: usize, k: K, v: V, bump: &'bump Bump) -> &mut V {
+        let len = self.len();
+        debug_assert!(index <= len);
+
+        // space for the new element
+        if len == self.cap() {
+            self.reserve(1, bump);
+        }
+
+        unsafe {
+            // infallible
+            // Shift both buffers over to make space, then write into the gap.
+            let kp = self.keys.ptr().add(index);
+            ptr::copy(kp, kp.offset(1), len - index);
+            ptr::write(kp, k);
+
+            let vp = self.vals.ptr().add(index);
+            ptr::copy(vp, vp.offset(1), len - index);
+            ptr::write(vp, v);
+
+            self.set_len(len + 1);
+
+            &mut *vp
+        }
+    }
+
+    pub fn get(&self, k: &K) -> Option<&V> {
+        self.binary_search_by(|v| v.cmp(k))
+            .ok()
+            // SAFETY: binary_search_by guarantees x < len
+            .map(|x| unsafe { self.values().get_unchecked(x) })
+    }
+
+    pub fn get_mut(&mut self, k: &K) -> Option<&mut V> {
+        self.binary_search_by(|v| v.cmp(k))
+            .ok()
+            // SAFETY: binary_search_by guarantees x < len
+            .map(|x| unsafe { self.values_mut().get_unchecked_mut(x) })
+    }
+
+    pub fn insert(&mut self, k: K, v: V, bump: &'bump Bump) -> Option<V> {
+        match self.binary_search_by(|x| x.cmp(&k)) {
+            // SAFETY: binary_search_by guarantees found < len
+            Ok(found) => Some(unsafe { ptr::replace(self.vals.ptr().add(found), v) }),
+            Err(none) => {
+                self.insert_at_ix(none, k, v, bump);
+                None
+            }
+        }
+    }
+
+    pub fn get_or_insert<F>(&mut self, k: K, mut vf: F, bump: &'bump Bump) -> &mut V
+    where
+        F: FnMut() -> V,
+    {
+        match self.binary_search_by(|v| v.cmp(&k)) {
+            // SAFETY: binary_search_by guarantees found < len
+            Ok(found) => unsafe { self.values_mut().get_unchecked_mut(found) },
+            Err(none) => self.insert_at_ix(none, k, vf(), bump),
+        }
+    }
+
+    #[inline]
+    pub fn binary_search_by<'a, F>(&'a self, mut f: F) -> Result<usize, usize>
+    where
+        F: FnMut(&'a K) -> Ordering,
+    {
+        let keys = self.keys();
+        let mut size = keys.len();
+        let mut left = 0;
+        let mut right = size;
+        while left < right {
+            let mid = left + size / 2;
+
+            // SAFETY: `mid < size`: `mid` is limited by `[left; right)` bound.
+            let cmp = f(unsafe { keys.get_unchecked(mid) });
+
+            // See `nested::Map::binary_search_by` for why this isn't a match.
+            if cmp == Ordering::Less {
+                left = mid + 1;
+            } else if cmp == Ordering::Greater {
+                right = mid;
+            } else {
+                // SAFETY: same as the `get_unchecked` above
+                unsafe { std::hint::assert_unchecked(mid < self.len()) };
+                return Ok(mid);
+            }
+
+            size = right - left;
+        }
+        Err(left)
+    }
+}
+
+impl<'bump, K: 'bump, V: 'bump> Drop for Map<'bump, K, V> {
+    fn drop(&mut self) {
+        // As with `BumpVec`, the arena owns the buffers but not the entries
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.keys.ptr(), self.len));
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.vals.ptr(), self.len));
+        }
+    }
+}
+
+impl<'bump, K: 'bump + Ord, V: 'bump> Default for Map<'bump, K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'bump, K, V> fmt::Debug for Map<'bump, K, V>
+where
+    K: 'bump + Ord + fmt::Debug,
+    V: 'bump + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+pub struct Trie<'a, T>(Map<'a, T, Self>);
+
+impl<'bump, V, const N: usize> Oneshot<'bump, N> for Trie<'bump, V>
+where
+    V: Ord + Clone + std::fmt::Debug + 'bump,
+{
+    type Value = V;
+    type IVal = &'bump V;
+    type KeyIter<const M: usize>
+        = iter_type!('bump, &'bump Self::Value)
+    where
+        Self: 'bump;
+    type Cursor<'a>
+        = NodeCursor<'a, Self>
+    where
+        Self: 'a;
+
+    fn from_iter<I: IntoIterator<Item = [Self::Value; N]>>(iter: I, bump: &'bump Bump) -> Self {
+        let mut res = Self(Map::new());
+
+        for tuple in iter.into_iter().sorted() {
+            let mut trie = &mut res;
+            for v in tuple {
+                trie = trie.0.get_or_insert(v, || Self(Map::new()), bump);
+            }
+        }
+
+        res
+    }
+
+    fn open(&self) -> NodeCursor<'_, Self> {
+        NodeCursor::new(self)
+    }
+
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
+        // Linear merge over the key buffers only.
+        let keys = |t: &'bump Self| t.0.keys().iter().map(|k| (k, ()));
+        super::intersect_sorted(keys(self), others.map(keys))
+            .map(|(k, _, _)| k)
+            .into_iter_type()
+    }
+}
+
+impl<'a, 'bump: 'a, V> Cursor<'a> for NodeCursor<'a, Trie<'bump, V>>
+where
+    V: Ord + 'bump,
+{
+    type Value = V;
+    type Keys = slice::Iter<'a, V>;
+    type Intersect<const M: usize> = iter_type!('a, (&'a V, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.node.0.keys().len()
+    }
+
+    fn keys(&self) -> Self::Keys {
+        self.node.0.keys().iter()
+    }
+
+    fn advance(&self, v: &V) -> Option<Self> {
+        self.node.0.get(v).map(|t| self.child(t))
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        let children = |c: Self| c.node.0.iter().map(move |(k, t)| (k, c.child(t)));
+        super::intersect_sorted(children(*self), others.map(children)).into_iter_type()
+    }
+}
+
+impl<V> Storable for Trie<'static, V> {
+    type Trie<'bump> = Trie<'bump, V>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'bump, V: Ord + 'bump> Stats for Trie<'bump, V> {
+    fn stats(&self) -> TrieStats {
+        TrieStats::walk(NodeCursor::new(self), |c| {
+            let m = &c.node.0;
+            let bytes = m.keys.cap() * mem::size_of::<V>() + m.vals.cap() * mem::size_of::<Self>();
+            (m.cap(), bytes)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use bumpalo::Bump;
+
+    use crate::Oneshot;
+
+    use super::{Map, Trie};
+
+    #[test]
+    fn drops() {
+        let a = Bump::new();
+        let vals = (0..10).map(Rc::new).collect::<Vec<_>>();
+        let t: Trie<Rc<i32>> = Oneshot::from_iter(
+            (0..10).map(|i| [vals[i].clone(), vals[(i + 1) % 10].clone()]),
+            &a,
+        );
+        assert!(vals.iter().all(|v| Rc::strong_count(v) > 1));
+
+        drop(t);
+        assert!(vals.iter().all(|v| Rc::strong_count(v) == 1));
+    }
+
+    #[test]
+    fn map_api() {
+        let a = Bump::new();
+        let mut m = Map::new();
+        for k in [5, 1, 4, 2, 3] {
+            assert_eq!(m.insert(k, k * 10, &a), None);
+        }
+        assert_eq!(m.insert(4, 41, &a), Some(40));
+        *m.get_or_insert(6, || 0, &a) += 60;
+
+        assert_eq!(m.keys(), &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(m.values(), &[10, 20, 30, 41, 50, 60]);
+        assert_eq!(m.get(&3), Some(&30));
+        assert_eq!(m.get(&7), None);
+    }
+
+    #[test]
+    fn iter_keys() {
+        let a = Bump::new();
+        let t = Trie::from_iter((0..10).map(|x| [x]), &a);
+        let t2 = Trie::from_iter((0..10).step_by(2).map(|x| [x]), &a);
+
+        let v: usize = <Trie<'_, i32> as Oneshot<1>>::intersect(&t, [&t2]).count();
+        assert_eq!(v, 5);
+    }
+}
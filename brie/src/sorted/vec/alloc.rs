@@ -17,11 +17,11 @@
 use core::{
     cmp, fmt, mem,
     ptr::{self, NonNull},
-    usize,
 };
 
+#[cfg(feature = "nightly")]
 use core::alloc::Allocator;
-pub use core::alloc::{AllocError, Layout, LayoutError};
+pub use core::alloc::{Layout, LayoutError};
 
 use bumpalo::Bump;
 
@@ -29,6 +29,11 @@ fn new_layout_err() -> LayoutError {
     Layout::from_size_align(1, 3).unwrap_err()
 }
 
+/// The allocator couldn't hand out a block, either because it ran out of
+/// memory or because the block didn't fit its constraints.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AllocError;
+
 pub fn handle_alloc_error(layout: Layout) -> ! {
     panic!("encountered allocation error: {:?}", layout)
 }
@@ -187,7 +192,7 @@ impl fmt::Display for CannotReallocInPlace {
 ///    currently allocated via an allocator `a`, then it is legal to
 ///    use that layout to deallocate it, i.e. `a.dealloc(ptr, k);`.
 ///
-/// # Unsafety
+/// # Safety
 ///
 /// The `Alloc` trait is an `unsafe` trait for a number of reasons, and
 /// implementors must ensure that they adhere to these contracts:
@@ -739,7 +744,7 @@ pub unsafe trait Alloc {
         match (Layout::array::<T>(n_old), Layout::array::<T>(n_new)) {
             (Ok(ref k_old), Ok(ref k_new)) if k_old.size() > 0 && k_new.size() > 0 => {
                 debug_assert!(k_old.align() == k_new.align());
-                self.realloc(ptr.cast(), k_old.clone(), k_new.size())
+                self.realloc(ptr.cast(), *k_old, k_new.size())
                     .map(NonNull::cast)
             }
             _ => Err(AllocError),
@@ -780,10 +785,13 @@ pub unsafe trait Alloc {
     }
 }
 
-unsafe impl<'a> Alloc for &'a Bump {
+#[cfg(feature = "nightly")]
+unsafe impl Alloc for &Bump {
     #[inline(always)]
     unsafe fn alloc(&mut self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
-        self.allocate(layout).map(|x| x.as_non_null_ptr())
+        self.allocate(layout)
+            .map(|x| x.as_non_null_ptr())
+            .map_err(|_| AllocError)
     }
 
     #[inline]
@@ -808,10 +816,44 @@ unsafe impl<'a> Alloc for &'a Bump {
         if new_size <= old_size {
             self.shrink(ptr, layout, new_layout)
                 .map(|x| x.as_non_null_ptr())
+                .map_err(|_| AllocError)
         } else {
             self.grow(ptr, layout, new_layout)
                 .map(|x| x.as_non_null_ptr())
+                .map_err(|_| AllocError)
+        }
+    }
+}
+
+/// Without `Allocator`, only `Bump`'s own API is available: blocks can't be
+/// given back or resized in place, so shrinking keeps the old block and
+/// growing copies into a new one.
+#[cfg(not(feature = "nightly"))]
+unsafe impl Alloc for &Bump {
+    #[inline(always)]
+    unsafe fn alloc(&mut self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        self.try_alloc_layout(layout).map_err(|_| AllocError)
+    }
+
+    #[inline]
+    unsafe fn dealloc(&mut self, _ptr: NonNull<u8>, _layout: Layout) {}
+
+    #[inline]
+    unsafe fn realloc(
+        &mut self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> Result<NonNull<u8>, AllocError> {
+        let old_size = layout.size();
+        if new_size <= old_size {
+            return Ok(ptr);
         }
+
+        let new_layout = layout_from_size_align(new_size, layout.align());
+        let new = self.alloc(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_ptr(), old_size);
+        Ok(new)
     }
 }
 
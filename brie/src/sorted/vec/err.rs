@@ -11,6 +11,17 @@ pub enum CollectionAllocError {
     AllocError,
 }
 
+impl CollectionAllocError {
+    /// Panics like the infallible collection methods would have, for
+    /// infallible builders which share their fallible counterpart's code.
+    pub(crate) fn raise(self) -> ! {
+        match self {
+            CollectionAllocError::CapacityOverflow => panic!("capacity overflow"),
+            CollectionAllocError::AllocError => panic!("out of memory"),
+        }
+    }
+}
+
 // #[unstable(feature = "try_reserve", reason = "new API", issue="48043")]
 impl From<AllocError> for CollectionAllocError {
     #[inline]
@@ -1,12 +1,36 @@
 //! Defines an unmanaged vector type.
+//!
+//! [`BumpVec`] is a `Vec` which doesn't keep a pointer to its arena, so every
+//! method which might allocate takes the [`Bump`] explicitly. Traits which
+//! would have to allocate without one are methods instead: `Clone` is
+//! [`BumpVec::clone_in`], `Extend` is [`BumpVec::extend`], and `FromIterator`
+//! is [`BumpVec::from_iter`].
+//!
+//! Dropping a [`BumpVec`] drops its elements, but leaves its buffer to the
+//! arena, which frees every buffer at once when it's dropped or reset. Tries
+//! built on [`BumpVec`] can hold values which own memory elsewhere, like
+//! `String`s, without leaking it.
 
 mod alloc;
 mod err;
 mod raw;
 
-use std::{fmt, mem, ops, ptr, slice};
+pub use err::CollectionAllocError;
 
-use bumpalo::{Bump, boxed::Box};
+use std::{
+    borrow::{Borrow, BorrowMut},
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    iter::FusedIterator,
+    marker::PhantomData,
+    mem,
+    ops::{self, Bound, RangeBounds},
+    ptr::{self, NonNull},
+    slice,
+};
+
+use bumpalo::{boxed::Box, Bump};
 pub use raw::*;
 
 pub struct BumpVec<'bump, T: 'bump> {
@@ -36,10 +60,59 @@ impl<'bump, T: 'bump> BumpVec<'bump, T> {
         self.len
     }
 
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buf.cap()
+    }
+
     pub fn reserve(&mut self, additional: usize, bump: &'bump Bump) {
         self.buf.reserve(self.len, additional, bump);
     }
 
+    pub fn reserve_exact(&mut self, additional: usize, bump: &'bump Bump) {
+        self.buf.reserve_exact(self.len, additional, bump);
+    }
+
+    /// Like [`BumpVec::with_capacity_in`], but returns an error instead of
+    /// panicking if the arena can't hand out `capacity` elements.
+    pub fn try_with_capacity_in(
+        capacity: usize,
+        bump: &'bump Bump,
+    ) -> Result<Self, CollectionAllocError> {
+        let mut v = Self::new();
+        v.try_reserve_exact(capacity, bump)?;
+        Ok(v)
+    }
+
+    pub fn try_reserve(
+        &mut self,
+        additional: usize,
+        bump: &'bump Bump,
+    ) -> Result<(), CollectionAllocError> {
+        self.buf.try_reserve(self.len, additional, bump)
+    }
+
+    pub fn try_reserve_exact(
+        &mut self,
+        additional: usize,
+        bump: &'bump Bump,
+    ) -> Result<(), CollectionAllocError> {
+        self.buf.try_reserve_exact(self.len, additional, bump)
+    }
+
+    /// Gives back as much spare capacity as the arena allows, which is only
+    /// possible if this is its most recent allocation.
+    pub fn shrink_to_fit(&mut self, bump: &'bump Bump) {
+        if self.capacity() != self.len {
+            self.buf.shrink_to_fit(self.len, bump);
+        }
+    }
+
     #[inline]
     pub fn push(&mut self, value: T, bump: &'bump Bump) {
         // This will panic or abort if we would allocate > isize::MAX bytes
@@ -54,8 +127,242 @@ impl<'bump, T: 'bump> BumpVec<'bump, T> {
         }
     }
 
+    /// Like [`BumpVec::push`], but hands `value` back along with the error if
+    /// there's no room for it.
+    #[inline]
+    pub fn try_push(
+        &mut self,
+        value: T,
+        bump: &'bump Bump,
+    ) -> Result<(), (T, CollectionAllocError)> {
+        if self.len == self.buf.cap() {
+            if let Err(e) = self.try_reserve(1, bump) {
+                return Err((value, e));
+            }
+        }
+        unsafe {
+            let end = self.buf.ptr().add(self.len);
+            ptr::write(end, value);
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            None
+        } else {
+            unsafe {
+                self.len -= 1;
+                Some(ptr::read(self.buf.ptr().add(self.len)))
+            }
+        }
+    }
+
+    /// Inserts `element` at `index`, shifting everything after it along.
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, element: T, bump: &'bump Bump) {
+        if self.len == self.buf.cap() {
+            self.reserve(1, bump);
+        }
+        self.insert_within_capacity(index, element);
+    }
+
+    /// Like [`BumpVec::insert`], but hands `element` back along with the
+    /// error if there's no room for it.
+    ///
+    /// Panics if `index > len`.
+    pub fn try_insert(
+        &mut self,
+        index: usize,
+        element: T,
+        bump: &'bump Bump,
+    ) -> Result<(), (T, CollectionAllocError)> {
+        if self.len == self.buf.cap() {
+            if let Err(e) = self.try_reserve(1, bump) {
+                return Err((element, e));
+            }
+        }
+        self.insert_within_capacity(index, element);
+        Ok(())
+    }
+
+    fn insert_within_capacity(&mut self, index: usize, element: T) {
+        let len = self.len;
+        assert!(
+            index <= len,
+            "insertion index (is {}) should be <= len (is {})",
+            index,
+            len
+        );
+        debug_assert!(len < self.buf.cap());
+
+        unsafe {
+            let p = self.buf.ptr().add(index);
+            // Shift everything over to make space, duplicating the `index`th
+            // element into two consecutive places, then overwrite the first.
+            ptr::copy(p, p.add(1), len - index);
+            ptr::write(p, element);
+            self.len = len + 1;
+        }
+    }
+
+    /// Removes and returns the element at `index`, shifting everything after
+    /// it back.
+    ///
+    /// Panics if `index >= len`.
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len;
+        assert!(
+            index < len,
+            "removal index (is {}) should be < len (is {})",
+            index,
+            len
+        );
+
+        unsafe {
+            let p = self.buf.ptr().add(index);
+            let ret = ptr::read(p);
+            ptr::copy(p.add(1), p, len - index - 1);
+            self.len = len - 1;
+            ret
+        }
+    }
+
+    /// Removes and returns the element at `index`, replacing it with the
+    /// last element. Doesn't preserve order, but is O(1).
+    ///
+    /// Panics if `index >= len`.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let len = self.len;
+        assert!(
+            index < len,
+            "swap_remove index (is {}) should be < len (is {})",
+            index,
+            len
+        );
+
+        unsafe {
+            let base = self.buf.ptr();
+            let ret = ptr::read(base.add(index));
+            ptr::copy(base.add(len - 1), base.add(index), 1);
+            self.len = len - 1;
+            ret
+        }
+    }
+
+    /// Keeps only the elements for which `f` returns `true`, in order.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain_mut(|x| f(x))
+    }
+
+    pub fn retain_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let len = self.len;
+        let mut deleted = 0;
+        {
+            // Swapping keeps the vector valid if `f` panics
+            let v = self.as_mut_slice();
+            for i in 0..len {
+                if !f(&mut v[i]) {
+                    deleted += 1;
+                } else if deleted > 0 {
+                    v.swap(i - deleted, i);
+                }
+            }
+        }
+        if deleted > 0 {
+            self.truncate(len - deleted);
+        }
+    }
+
+    /// Removes the elements in `range`, yielding them. Whatever the iterator
+    /// doesn't get to is dropped along with it.
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn drain<R>(&mut self, range: R) -> Drain<'_, 'bump, T>
+    where
+        R: RangeBounds<usize>,
+    {
+        let len = self.len;
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => len,
+        };
+        assert!(
+            start <= end,
+            "drain start (is {}) should be <= end (is {})",
+            start,
+            end
+        );
+        assert!(
+            end <= len,
+            "drain end (is {}) should be <= len (is {})",
+            end,
+            len
+        );
+
+        unsafe {
+            // If the Drain is leaked, so are the drained elements and the tail
+            self.len = start;
+            let drained = slice::from_raw_parts(self.buf.ptr().add(start), end - start);
+            Drain {
+                tail_start: end,
+                tail_len: len - end,
+                iter: drained.iter(),
+                vec: NonNull::from(self),
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    /// Splits off everything from `at` onwards into a new vector.
+    ///
+    /// Panics if `at > len`.
+    pub fn split_off(&mut self, at: usize, bump: &'bump Bump) -> Self {
+        assert!(at <= self.len, "`at` out of bounds");
+
+        let other_len = self.len - at;
+        let mut other = Self::with_capacity_in(other_len, bump);
+        unsafe {
+            self.len = at;
+            ptr::copy_nonoverlapping(self.buf.ptr().add(at), other.buf.ptr(), other_len);
+            other.len = other_len;
+        }
+        other
+    }
+
+    /// Moves every element of `other` onto the end of this vector, leaving
+    /// `other` empty.
+    pub fn append(&mut self, other: &mut Self, bump: &'bump Bump) {
+        let count = other.len;
+        self.reserve(count, bump);
+        unsafe {
+            ptr::copy_nonoverlapping(other.buf.ptr(), self.buf.ptr().add(self.len), count);
+            other.len = 0;
+        }
+        self.len += count;
+    }
+
     #[inline]
-    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I, bump: &'bump Bump) {
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    pub fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I, bump: &'bump Bump) {
         let iter = iter.into_iter();
         self.reserve(iter.size_hint().0, bump);
 
@@ -70,6 +377,52 @@ impl<'bump, T: 'bump> BumpVec<'bump, T> {
         v
     }
 
+    /// Like [`BumpVec::extend`], but stops at the first element there's no
+    /// room for. Everything before it has been pushed; it and the rest of
+    /// `iter` are dropped.
+    pub fn try_extend<I: IntoIterator<Item = T>>(
+        &mut self,
+        iter: I,
+        bump: &'bump Bump,
+    ) -> Result<(), CollectionAllocError> {
+        let iter = iter.into_iter();
+        self.try_reserve(iter.size_hint().0, bump)?;
+
+        for t in iter {
+            self.try_push(t, bump).map_err(|(_, e)| e)?;
+        }
+        Ok(())
+    }
+
+    pub fn try_from_iter<I: IntoIterator<Item = T>>(
+        iter: I,
+        bump: &'bump Bump,
+    ) -> Result<Self, CollectionAllocError> {
+        let mut v = Self::new();
+        v.try_extend(iter, bump)?;
+        Ok(v)
+    }
+
+    /// Resizes to `new_len`, filling any new slots by calling `f`.
+    pub fn resize_with<F>(&mut self, new_len: usize, f: F, bump: &'bump Bump)
+    where
+        F: FnMut() -> T,
+    {
+        if new_len > self.len {
+            self.extend(std::iter::repeat_with(f).take(new_len - self.len), bump);
+        } else {
+            self.truncate(new_len);
+        }
+    }
+
+    pub fn dedup_by_key<F, K>(&mut self, mut key: F)
+    where
+        F: FnMut(&mut T) -> K,
+        K: PartialEq,
+    {
+        self.dedup_by(|a, b| key(a) == key(b))
+    }
+
     pub fn dedup_by<F>(&mut self, same_bucket: F)
     where
         F: FnMut(&mut T, &mut T) -> bool,
@@ -100,8 +453,24 @@ impl<'bump, T: 'bump> BumpVec<'bump, T> {
         }
     }
 
+    /// Sets the length without dropping or initializing anything.
+    ///
+    /// # Safety
+    ///
+    /// `new_len` must be at most the capacity, and the first `new_len`
+    /// elements must be initialized.
+    #[inline]
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        self.len = new_len;
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        self
+    }
+
     pub fn as_mut_slice(&mut self) -> &mut [T] {
-        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr() as *mut T, self.len()) }
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.len()) }
     }
 
     pub fn into_boxed_slice(mut self) -> Box<'bump, [T]> {
@@ -115,6 +484,24 @@ impl<'bump, T: 'bump> BumpVec<'bump, T> {
     }
 }
 
+impl<'bump, T: 'bump + Clone> BumpVec<'bump, T> {
+    /// Clones this vector into `bump`.
+    pub fn clone_in(&self, bump: &'bump Bump) -> Self {
+        let mut v = Self::with_capacity_in(self.len, bump);
+        v.extend_from_slice(self, bump);
+        v
+    }
+
+    pub fn extend_from_slice(&mut self, other: &[T], bump: &'bump Bump) {
+        self.extend(other.iter().cloned(), bump)
+    }
+
+    /// Resizes to `new_len`, filling any new slots with clones of `value`.
+    pub fn resize(&mut self, new_len: usize, value: T, bump: &'bump Bump) {
+        self.resize_with(new_len, || value.clone(), bump)
+    }
+}
+
 impl<'bump, T: 'bump + PartialEq> BumpVec<'bump, T> {
     /// Removes consecutive repeated elements in the vector according to the
     /// [`PartialEq`] trait implementation.
@@ -168,6 +555,272 @@ impl<'bump, T: 'bump + fmt::Debug> fmt::Debug for BumpVec<'bump, T> {
     }
 }
 
+impl<'bump, T: 'bump> Drop for BumpVec<'bump, T> {
+    fn drop(&mut self) {
+        // The buffer belongs to the arena, so only the elements need dropping
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) }
+    }
+}
+
+impl<'bump, T: 'bump> Default for BumpVec<'bump, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'bump, T: 'bump> AsRef<[T]> for BumpVec<'bump, T> {
+    fn as_ref(&self) -> &[T] {
+        self
+    }
+}
+
+impl<'bump, T: 'bump> AsMut<[T]> for BumpVec<'bump, T> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+impl<'bump, T: 'bump> Borrow<[T]> for BumpVec<'bump, T> {
+    fn borrow(&self) -> &[T] {
+        self
+    }
+}
+
+impl<'bump, T: 'bump> BorrowMut<[T]> for BumpVec<'bump, T> {
+    fn borrow_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+impl<'a, 'b, A, B> PartialEq<BumpVec<'b, B>> for BumpVec<'a, A>
+where
+    A: PartialEq<B>,
+{
+    #[inline]
+    fn eq(&self, other: &BumpVec<'b, B>) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<'bump, A, B> PartialEq<[B]> for BumpVec<'bump, A>
+where
+    A: PartialEq<B>,
+{
+    #[inline]
+    fn eq(&self, other: &[B]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<'bump, 'a, A, B> PartialEq<&'a [B]> for BumpVec<'bump, A>
+where
+    A: PartialEq<B>,
+{
+    #[inline]
+    fn eq(&self, other: &&'a [B]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<'bump, A, B, const N: usize> PartialEq<[B; N]> for BumpVec<'bump, A>
+where
+    A: PartialEq<B>,
+{
+    #[inline]
+    fn eq(&self, other: &[B; N]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<'bump, T: 'bump + Eq> Eq for BumpVec<'bump, T> {}
+
+impl<'bump, T: 'bump + PartialOrd> PartialOrd for BumpVec<'bump, T> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        PartialOrd::partial_cmp(&**self, &**other)
+    }
+}
+
+impl<'bump, T: 'bump + Ord> Ord for BumpVec<'bump, T> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        Ord::cmp(&**self, &**other)
+    }
+}
+
+impl<'bump, T: 'bump + Hash> Hash for BumpVec<'bump, T> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&**self, state)
+    }
+}
+
+impl<'a, 'bump, T: 'bump> IntoIterator for &'a BumpVec<'bump, T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, 'bump, T: 'bump> IntoIterator for &'a mut BumpVec<'bump, T> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> slice::IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl<'bump, T: 'bump> IntoIterator for BumpVec<'bump, T> {
+    type Item = T;
+    type IntoIter = IntoIter<'bump, T>;
+
+    fn into_iter(self) -> IntoIter<'bump, T> {
+        IntoIter {
+            end: self.len,
+            start: 0,
+            vec: self,
+        }
+    }
+}
+
+/// An iterator moving elements out of a [`BumpVec`]. Elements which aren't
+/// yielded are dropped along with it.
+pub struct IntoIter<'bump, T: 'bump> {
+    vec: BumpVec<'bump, T>,
+    /// Elements in `start..end` haven't been moved out yet.
+    start: usize,
+    end: usize,
+}
+
+impl<'bump, T: 'bump> IntoIter<'bump, T> {
+    /// The elements which haven't been yielded yet.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.vec.buf.ptr().add(self.start), self.end - self.start) }
+    }
+}
+
+impl<'bump, T: 'bump> Iterator for IntoIter<'bump, T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            let ix = self.start;
+            self.start += 1;
+            Some(unsafe { ptr::read(self.vec.buf.ptr().add(ix)) })
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.start;
+        (len, Some(len))
+    }
+}
+
+impl<'bump, T: 'bump> DoubleEndedIterator for IntoIter<'bump, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            self.end -= 1;
+            Some(unsafe { ptr::read(self.vec.buf.ptr().add(self.end)) })
+        }
+    }
+}
+
+impl<'bump, T: 'bump> ExactSizeIterator for IntoIter<'bump, T> {}
+
+impl<'bump, T: 'bump> FusedIterator for IntoIter<'bump, T> {}
+
+impl<'bump, T: 'bump + fmt::Debug> fmt::Debug for IntoIter<'bump, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("IntoIter").field(&self.as_slice()).finish()
+    }
+}
+
+impl<'bump, T: 'bump> Drop for IntoIter<'bump, T> {
+    fn drop(&mut self) {
+        unsafe {
+            // Everything outside `start..end` was moved out already
+            self.vec.len = 0;
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.vec.buf.ptr().add(self.start),
+                self.end - self.start,
+            ));
+        }
+    }
+}
+
+/// An iterator removing a range of elements from a [`BumpVec`], made by
+/// [`BumpVec::drain`].
+pub struct Drain<'a, 'bump: 'a, T: 'bump> {
+    /// Where the elements after the drained range start, and how many there
+    /// are; they're moved back once the drain is dropped.
+    tail_start: usize,
+    tail_len: usize,
+    iter: slice::Iter<'a, T>,
+    vec: NonNull<BumpVec<'bump, T>>,
+    _marker: PhantomData<&'a mut BumpVec<'bump, T>>,
+}
+
+impl<'a, 'bump, T> Iterator for Drain<'a, 'bump, T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.iter.next().map(|e| unsafe { ptr::read(e) })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, 'bump, T> DoubleEndedIterator for Drain<'a, 'bump, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        self.iter.next_back().map(|e| unsafe { ptr::read(e) })
+    }
+}
+
+impl<'a, 'bump, T> ExactSizeIterator for Drain<'a, 'bump, T> {}
+
+impl<'a, 'bump, T> FusedIterator for Drain<'a, 'bump, T> {}
+
+impl<'a, 'bump, T: fmt::Debug> fmt::Debug for Drain<'a, 'bump, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Drain").field(&self.iter.as_slice()).finish()
+    }
+}
+
+impl<'a, 'bump, T> Drop for Drain<'a, 'bump, T> {
+    fn drop(&mut self) {
+        // Drop whatever wasn't yielded
+        self.for_each(drop);
+
+        if self.tail_len > 0 {
+            unsafe {
+                let vec = self.vec.as_mut();
+                let start = vec.len;
+                if self.tail_start != start {
+                    let src = vec.buf.ptr().add(self.tail_start);
+                    let dst = vec.buf.ptr().add(start);
+                    ptr::copy(src, dst, self.tail_len);
+                }
+                vec.len = start + self.tail_len;
+            }
+        }
+    }
+}
+
 fn partition_dedup_by<T, F>(s: &mut [T], mut same_bucket: F) -> (&mut [T], &mut [T])
 where
     F: FnMut(&mut T, &mut T) -> bool,
@@ -246,7 +899,7 @@ where
             if !same_bucket(&mut *ptr_read, &mut *prev_ptr_write) {
                 if next_read != next_write {
                     let ptr_write = prev_ptr_write.offset(1);
-                    mem::swap(&mut *ptr_read, &mut *ptr_write);
+                    ptr::swap(ptr_read, ptr_write);
                 }
                 next_write += 1;
             }
@@ -276,11 +929,6 @@ impl<'a> SetLenOnDrop<'a> {
         }
     }
 
-    #[inline]
-    fn increment_len(&mut self, increment: usize) {
-        self.local_len += increment;
-    }
-
     #[inline]
     fn decrement_len(&mut self, decrement: usize) {
         self.local_len -= decrement;
@@ -293,3 +941,108 @@ impl<'a> Drop for SetLenOnDrop<'a> {
         *self.len = self.local_len;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use bumpalo::Bump;
+
+    use super::{BumpVec, CollectionAllocError};
+
+    #[test]
+    fn edits() {
+        let a = Bump::new();
+        let mut v = BumpVec::from_iter(0..5, &a);
+
+        v.insert(2, 10, &a);
+        assert_eq!(v, [0, 1, 10, 2, 3, 4]);
+        assert_eq!(v.remove(0), 0);
+        assert_eq!(v.swap_remove(0), 1);
+        assert_eq!(v, [4, 10, 2, 3]);
+        assert_eq!(v.pop(), Some(3));
+
+        v.retain(|x| x % 2 == 0);
+        assert_eq!(v, [4, 10, 2]);
+
+        let mut tail = v.split_off(1, &a);
+        assert_eq!((&v[..], &tail[..]), (&[4][..], &[10, 2][..]));
+        v.append(&mut tail, &a);
+        assert!(tail.is_empty());
+        assert_eq!(v.clone_in(&a), v);
+
+        v.resize(5, 7, &a);
+        assert_eq!(v, [4, 10, 2, 7, 7]);
+        v.clear();
+        assert_eq!(v.pop(), None);
+    }
+
+    #[test]
+    fn fallible() {
+        let a = Bump::new();
+        let mut v = BumpVec::try_from_iter(0..4u64, &a).unwrap();
+        assert_eq!(
+            v.try_reserve(usize::MAX, &a),
+            Err(CollectionAllocError::CapacityOverflow)
+        );
+
+        // Fill up the arena's current chunk; it can't get another. Whatever
+        // doesn't fit is handed back.
+        a.set_allocation_limit(Some(a.allocated_bytes()));
+        while v.try_push(0, &a).is_ok() {}
+        assert_eq!(
+            v.try_push(7, &a),
+            Err((7, CollectionAllocError::AllocError))
+        );
+        assert_eq!(
+            v.try_insert(0, 8, &a),
+            Err((8, CollectionAllocError::AllocError))
+        );
+        assert!(v.try_extend(0..100, &a).is_err());
+        assert_eq!(v.len(), v.capacity());
+    }
+
+    #[test]
+    fn drain() {
+        let a = Bump::new();
+        let mut v = BumpVec::from_iter((0..6).map(|x| x.to_string()), &a);
+
+        // Only take one; the rest of the range still goes
+        assert_eq!(v.drain(1..4).next().as_deref(), Some("1"));
+        assert_eq!(v, ["0", "4", "5"]);
+
+        assert_eq!(v.drain(..).rev().collect::<Vec<_>>(), vec!["5", "4", "0"]);
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn drops() {
+        let a = Bump::new();
+        let rc = Rc::new(());
+
+        let mut v = BumpVec::from_iter((0..8).map(|_| rc.clone()), &a);
+        v.truncate(6);
+        v.drain(1..3).next();
+        assert_eq!(Rc::strong_count(&rc), 5);
+
+        // Moved out or not, every element is dropped exactly once
+        let mut it = v.split_off(2, &a).into_iter();
+        let first = it.next();
+        drop(it);
+        assert_eq!(Rc::strong_count(&rc), 4);
+        drop((v, first));
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn into_iter() {
+        let a = Bump::new();
+        let v = BumpVec::from_iter(["a", "b", "c"].map(String::from), &a);
+
+        let mut it = v.into_iter();
+        assert_eq!(it.next_back().as_deref(), Some("c"));
+        assert_eq!(it.len(), 2);
+        assert_eq!(it.as_slice(), ["a", "b"]);
+        assert_eq!(it.collect::<Vec<_>>(), vec!["a", "b"]);
+    }
+}
@@ -23,7 +23,7 @@ use core::{
 };
 use std::marker::PhantomData;
 
-use core::alloc::{Allocator, Layout};
+use core::alloc::Layout;
 
 // use alloc::{handle_alloc_error, Alloc, Layout, UnstableLayoutMethods};
 // use err::{CollectionAllocErr, CollectionAllocErr::*};
@@ -64,6 +64,13 @@ pub struct RawVec<'a, T: 'a> {
     _marker: PhantomData<&'a Bump>,
 }
 
+// A RawVec only borrows its arena in name: every method which allocates takes
+// the `&Bump` as an argument. `Bump` isn't `Sync`, so that can only happen on
+// the thread which owns the arena, and otherwise a RawVec is as thread-safe as
+// the `T`s in it, just like a `Vec<T>`.
+unsafe impl<'a, T: Send> Send for RawVec<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for RawVec<'a, T> {}
+
 impl<'a, T> RawVec<'a, T> {
     /// Like `new` but parameterized over the choice of allocator for
     /// the returned RawVec.
@@ -74,7 +81,7 @@ impl<'a, T> RawVec<'a, T> {
 
         // Unique::empty() doubles as "unallocated" and "zero-sized allocation"
         RawVec {
-            ptr: unsafe { NonNull::new_unchecked(mem::align_of::<T>() as *mut T) },
+            ptr: NonNull::dangling(),
             // FIXME(mark-i-m): use `cap` when ifs are allowed in const
             cap: [0, !0][(mem::size_of::<T>() == 0) as usize],
             _marker: PhantomData,
@@ -95,7 +102,7 @@ impl<'a, T> RawVec<'a, T> {
         RawVec::allocate_in(cap, true, a)
     }
 
-    fn allocate_in(cap: usize, zeroed: bool, a: &'a Bump) -> Self {
+    fn allocate_in(cap: usize, zeroed: bool, mut a: &'a Bump) -> Self {
         let elem_size = mem::size_of::<T>();
 
         let alloc_size = cap
@@ -109,10 +116,12 @@ impl<'a, T> RawVec<'a, T> {
         } else {
             let align = mem::align_of::<T>();
             let layout = Layout::from_size_align(alloc_size, align).unwrap();
-            let result = if zeroed {
-                a.allocate_zeroed(layout)
-            } else {
-                a.allocate(layout)
+            let result = unsafe {
+                if zeroed {
+                    Alloc::alloc_zeroed(&mut a, layout)
+                } else {
+                    Alloc::alloc(&mut a, layout)
+                }
             };
             match result {
                 Ok(ptr) => ptr.cast(),
@@ -131,9 +140,11 @@ impl<'a, T> RawVec<'a, T> {
 impl<'a, T> RawVec<'a, T> {
     /// Reconstitutes a RawVec from a pointer, capacity, and allocator.
     ///
-    /// # Undefined Behavior
+    /// # Safety
     ///
-    /// The capacity cannot exceed `isize::MAX` (only a concern on 32-bit systems).
+    /// `ptr` must have been allocated in the arena with room for `cap`
+    /// elements, and the capacity cannot exceed `isize::MAX` (only a concern on
+    /// 32-bit systems).
     pub unsafe fn from_raw_parts_in(ptr: *mut T, cap: usize) -> Self {
         RawVec {
             ptr: NonNull::new_unchecked(ptr),
@@ -620,7 +631,7 @@ impl<'a, T> RawVec<'a, T> {
                 None => Alloc::alloc(&mut a, new_layout),
             };
 
-            if let (Err(core::alloc::AllocError), Infallible) = (&res, fallibility) {
+            if let (Err(_), Infallible) = (&res, fallibility) {
                 handle_alloc_error(new_layout);
             }
 
@@ -634,6 +645,10 @@ impl<'a, T> RawVec<'a, T> {
 
 impl<'a, T> RawVec<'a, T> {
     /// Frees the memory owned by the RawVec *without* trying to Drop its contents.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must have been allocated in `a`, and mustn't be used again.
     pub unsafe fn dealloc_buffer<'b: 'a>(&mut self, mut a: &'b Bump) {
         let elem_size = mem::size_of::<T>();
         if elem_size != 0 {
@@ -644,6 +659,12 @@ impl<'a, T> RawVec<'a, T> {
     }
 }
 
+impl<'a, T> Default for RawVec<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a, T> Drop for RawVec<'a, T> {
     /// The Drop impl for a RawVec is a no-op.
     /// It is up to the caller to deallocate the buffer if they want to.
@@ -664,7 +685,7 @@ impl<'a, T> Drop for RawVec<'a, T> {
 
 #[inline]
 fn alloc_guard(alloc_size: usize) -> Result<(), CollectionAllocError> {
-    if mem::size_of::<usize>() < 8 && alloc_size > ::core::isize::MAX as usize {
+    if mem::size_of::<usize>() < 8 && alloc_size > isize::MAX as usize {
         Err(CapacityOverflow)
     } else {
         Ok(())
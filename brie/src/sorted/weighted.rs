@@ -0,0 +1,300 @@
+//! Weighted sorted tries, for differential-style updates.
+//!
+//! Every tuple carries a signed weight, as in the Z-sets of differential
+//! dataflow: inserting adds to a tuple's weight, deleting subtracts from it,
+//! and a tuple whose weight reaches zero is removed from the trie.
+//!
+//! Each node also stores the sum of the weights beneath it, so the weight of
+//! a leaf is the weight of its tuple. Intersections yield the product of the
+//! matched nodes' weights; once every participant is at its last level this
+//! is exactly the weight of the joined tuple.
+
+use std::{
+    fmt, mem,
+    ops::{Add, Mul, Neg},
+};
+
+use bumpalo::Bump;
+
+use crate::{
+    stats::{Stats, TrieStats},
+    store::Storable,
+    Cursor, IntoIterType, NodeCursor, Oneshot,
+};
+
+use super::Map;
+
+/// A tuple weight: a ring with a zero and a one.
+pub trait Weight:
+    Copy + PartialEq + fmt::Debug + Add<Output = Self> + Mul<Output = Self> + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+}
+
+macro_rules! impl_weight {
+    ($($t:ty),*) => {
+        $(
+            impl Weight for $t {
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+            }
+        )*
+    };
+}
+
+impl_weight!(i8, i16, i32, i64, i128, isize);
+
+pub struct Trie<'bump, V, W = isize> {
+    map: Map<'bump, V, Self>,
+    weight: W,
+}
+
+impl<'bump, V, W> Trie<'bump, V, W>
+where
+    V: Ord + Clone + 'bump,
+    W: Weight,
+{
+    pub fn new() -> Self {
+        Self {
+            map: Map::new(),
+            weight: W::ZERO,
+        }
+    }
+
+    /// The summed weight of every tuple under this node.
+    #[inline]
+    pub fn weight(&self) -> W {
+        self.weight
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.len() == 0 && self.weight == W::ZERO
+    }
+
+    pub fn from_weighted_iter<I, const N: usize>(iter: I, bump: &'bump Bump) -> Self
+    where
+        I: IntoIterator<Item = ([V; N], W)>,
+    {
+        let mut res = Self::new();
+        for (tuple, w) in iter {
+            res.update(&tuple, w, bump);
+        }
+        res
+    }
+
+    /// Adds `w` to the weight of `tuple`, removing it if the weight
+    /// cancels out to zero.
+    pub fn update(&mut self, tuple: &[V], w: W, bump: &'bump Bump) {
+        if w != W::ZERO {
+            self.update_inner(tuple, w, bump);
+        }
+    }
+
+    #[inline]
+    pub fn insert(&mut self, tuple: &[V], bump: &'bump Bump) {
+        self.update(tuple, W::ONE, bump);
+    }
+
+    #[inline]
+    pub fn delete(&mut self, tuple: &[V], bump: &'bump Bump) {
+        self.update(tuple, -W::ONE, bump);
+    }
+
+    // Returns whether this node is now empty and should be removed from
+    // its parent.
+    fn update_inner(&mut self, tuple: &[V], w: W, bump: &'bump Bump) -> bool {
+        self.weight = self.weight + w;
+
+        if let Some((v, rest)) = tuple.split_first() {
+            let child = self.map.get_or_insert(v.clone(), Self::new, bump);
+            if child.update_inner(rest, w, bump) {
+                self.map.remove(v);
+            }
+        }
+
+        self.is_empty()
+    }
+
+    pub fn advance(&self, v: &V) -> Option<&Self> {
+        self.map.get(v)
+    }
+}
+
+impl<'bump, V, W> Default for Trie<'bump, V, W>
+where
+    V: Ord + Clone + 'bump,
+    W: Weight,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'bump, V: fmt::Debug, W: fmt::Debug> fmt::Debug for Trie<'bump, V, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Trie")
+            .field("weight", &self.weight)
+            .field("map", &self.map)
+            .finish()
+    }
+}
+
+impl<'bump, V, W, const N: usize> Oneshot<'bump, N> for Trie<'bump, V, W>
+where
+    V: Ord + Clone + fmt::Debug + 'bump,
+    W: Weight + 'bump,
+{
+    type Value = V;
+    type IVal = (&'bump V, W);
+    type KeyIter<const M: usize>
+        = iter_type!('bump, (&'bump V, W))
+    where
+        Self: 'bump;
+    type Cursor<'a>
+        = NodeCursor<'a, Self>
+    where
+        Self: 'a;
+
+    fn from_iter<I: IntoIterator<Item = [Self::Value; N]>>(iter: I, bump: &'bump Bump) -> Self {
+        Self::from_weighted_iter(iter.into_iter().map(|t| (t, W::ONE)), bump)
+    }
+
+    fn open(&self) -> NodeCursor<'_, Self> {
+        NodeCursor::new(self)
+    }
+
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
+        // Same linear merge as the unweighted trie, but we multiply the
+        // weights of the matched children.
+        let children = |t: &'bump Self| t.map.iter().map(|(k, child)| (k, child));
+        super::intersect_sorted(children(self), others.map(children))
+            .map(|(k, this, others)| (k, others.iter().fold(this.weight, |w, t| w * t.weight)))
+            .into_iter_type()
+    }
+}
+
+/// The weight of the tuples under a cursor is its node's [`Trie::weight`],
+/// so the weight of a join result is the product of its children's weights.
+impl<'a, 'bump: 'a, V, W> Cursor<'a> for NodeCursor<'a, Trie<'bump, V, W>>
+where
+    V: Ord + Clone + 'bump,
+    W: Weight + 'bump,
+{
+    type Value = V;
+    type Keys = iter_type!('a, &'a V);
+    type Intersect<const M: usize> = iter_type!('a, (&'a V, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.node.map.len()
+    }
+
+    fn keys(&self) -> Self::Keys {
+        self.node.map.iter().map(|(k, _)| k).into_iter_type()
+    }
+
+    fn advance(&self, v: &V) -> Option<Self> {
+        self.node.advance(v).map(|t| self.child(t))
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        let children = |c: Self| c.node.map.iter().map(move |(k, t)| (k, c.child(t)));
+        super::intersect_sorted(children(*self), others.map(children)).into_iter_type()
+    }
+}
+
+impl<V, W> Storable for Trie<'static, V, W> {
+    type Trie<'bump> = Trie<'bump, V, W>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'bump, V, W> Stats for Trie<'bump, V, W>
+where
+    V: Ord + Clone + 'bump,
+    W: Weight + 'bump,
+{
+    fn stats(&self) -> TrieStats {
+        TrieStats::walk(NodeCursor::new(self), |c| {
+            let cap = c.node.map.capacity();
+            (cap, cap * mem::size_of::<(V, Self)>())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+
+    use crate::{
+        join::{generic_join, Plan},
+        NodeCursor, Oneshot,
+    };
+
+    use super::Trie;
+
+    #[test]
+    fn cancel_out() {
+        let a = Bump::new();
+        let mut t: Trie<i32> = Trie::new();
+
+        t.insert(&[1, 2], &a);
+        t.insert(&[1, 3], &a);
+        t.update(&[2, 2], 3, &a);
+        t.delete(&[1, 2], &a);
+        assert_eq!(t.weight(), 4);
+        assert!(t.advance(&1).unwrap().advance(&2).is_none());
+
+        t.update(&[1, 3], -1, &a);
+        assert!(t.advance(&1).is_none());
+        assert_eq!(t.len(), 1);
+
+        t.update(&[2, 2], -3, &a);
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn intersect_weights() {
+        let a = Bump::new();
+        let r = Trie::from_weighted_iter([([1], 2), ([2], -1), ([3], 1)], &a);
+        let s = Trie::from_weighted_iter([([2], 5), ([3], 3), ([4], 1)], &a);
+
+        let v: Vec<_> = <Trie<i32, isize> as Oneshot<1>>::intersect(&r, [&s])
+            .map(|(k, w)| (*k, w))
+            .collect();
+        assert_eq!(v, vec![(2, -5), (3, 3)]);
+    }
+
+    #[test]
+    fn join_weights() {
+        let a = Bump::new();
+        let r: Trie<i32> = Trie::from_weighted_iter([([1, 2], 2), ([1, 3], -1), ([2, 3], 4)], &a);
+        let s: Trie<i32> = Trie::from_weighted_iter([([2, 5], 3), ([3, 6], 5)], &a);
+
+        // R(x, y) join S(y, z), weighted by the product of the leaves'
+        let plan = Plan::new(&[[0, 1], [1, 2]]);
+        let leaf =
+            |t: &Trie<i32>, k: [i32; 2]| t.advance(&k[0]).unwrap().advance(&k[1]).unwrap().weight();
+        let mut res = Vec::new();
+        generic_join(&plan, &[NodeCursor::new(&r), NodeCursor::new(&s)], |b| {
+            let [x, y, z] = [*b[0], *b[1], *b[2]];
+            res.push(([x, y, z], leaf(&r, [x, y]) * leaf(&s, [y, z])));
+        });
+        res.sort_unstable();
+        assert_eq!(res, vec![([1, 2, 5], 6), ([1, 3, 6], -5), ([2, 3, 6], 20)]);
+    }
+}
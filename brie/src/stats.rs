@@ -0,0 +1,224 @@
+//! How big a trie is, and how well its nodes are sized.
+//!
+//! Every backend implements [`Stats`]. The shape of each level comes from
+//! walking the trie with its [`Cursor`], so it means the same thing for
+//! every backend; slots and bytes are whatever that backend allocated.
+
+use std::fmt;
+
+use crate::Cursor;
+
+/// One level of a trie: every node `level` values below the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LevelStats {
+    /// Nodes at this level. The root is the only node at level 0.
+    pub nodes: usize,
+    /// Keys across every node at this level.
+    pub keys: usize,
+    /// The most keys in any one node at this level.
+    pub max_fanout: usize,
+    /// Slots allocated for those keys: buckets in hashed nodes, capacity in
+    /// sorted ones. Zero for layouts which don't store nodes separately.
+    pub slots: usize,
+}
+
+impl LevelStats {
+    /// The mean number of keys per node.
+    pub fn avg_fanout(&self) -> f64 {
+        if self.nodes == 0 {
+            0.0
+        } else {
+            self.keys as f64 / self.nodes as f64
+        }
+    }
+
+    /// The fraction of allocated slots holding a key, if this level's nodes
+    /// have slots of their own.
+    pub fn load_factor(&self) -> Option<f64> {
+        (self.slots > 0).then(|| self.keys as f64 / self.slots as f64)
+    }
+}
+
+/// Collisions in the single hash table behind [`crate::hash::Trie`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChainStats {
+    /// Buckets in the table.
+    pub slots: usize,
+    /// Buckets holding a key.
+    pub occupied: usize,
+    /// Keys which collided, and so live in the overflow array instead.
+    pub overflow: usize,
+    /// The most keys hashed to any one bucket.
+    pub max_len: usize,
+}
+
+impl ChainStats {
+    /// The mean number of keys per occupied bucket.
+    pub fn avg_len(&self) -> f64 {
+        if self.occupied == 0 {
+            0.0
+        } else {
+            (self.occupied + self.overflow) as f64 / self.occupied as f64
+        }
+    }
+
+    /// The fraction of buckets holding a key, or zero for an empty table.
+    pub fn load_factor(&self) -> f64 {
+        if self.slots == 0 {
+            0.0
+        } else {
+            self.occupied as f64 / self.slots as f64
+        }
+    }
+}
+
+/// Node statistics for a whole trie.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrieStats {
+    /// One entry per level from the root down; leaves aren't a level, so
+    /// there are as many levels as columns unless the trie is empty.
+    pub levels: Vec<LevelStats>,
+    /// Collision chains, for backends with one shared hash table.
+    pub chains: Option<ChainStats>,
+    /// Bytes taken up by the trie's buffers, including spare capacity. Hash
+    /// maps which don't expose their buckets are estimated from capacity.
+    pub bytes: usize,
+}
+
+impl TrieStats {
+    /// Walks every node under `root`, recording the shape of each level.
+    /// `node_size` gives the `(slots, bytes)` allocated for a node's own
+    /// storage, which are summed up too.
+    pub fn walk<'a, C, F>(root: C, mut node_size: F) -> Self
+    where
+        C: Cursor<'a>,
+        F: FnMut(&C) -> (usize, usize),
+    {
+        let mut res = Self::default();
+        let mut stack = vec![(0, root)];
+
+        while let Some((level, c)) = stack.pop() {
+            let fanout = c.len();
+            let (slots, bytes) = node_size(&c);
+
+            if res.levels.len() <= level {
+                res.levels.resize_with(level + 1, LevelStats::default);
+            }
+            let l = &mut res.levels[level];
+            l.nodes += 1;
+            l.keys += fanout;
+            l.max_fanout = l.max_fanout.max(fanout);
+            l.slots += slots;
+            res.bytes += bytes;
+
+            // Leaves have no keys, so they don't make a level of their own
+            stack.extend(
+                c.intersect_with([])
+                    .map(|(_, child, [])| (level + 1, child))
+                    .filter(|(_, child)| !child.is_empty()),
+            );
+        }
+
+        res
+    }
+
+    /// The number of distinct tuples: the keys at the bottom level.
+    pub fn tuples(&self) -> usize {
+        self.levels.last().map_or(0, |l| l.keys)
+    }
+}
+
+impl fmt::Display for TrieStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, l) in self.levels.iter().enumerate() {
+            write!(
+                f,
+                "level {}: {} nodes, {} keys, fanout {:.2} avg / {} max",
+                i,
+                l.nodes,
+                l.keys,
+                l.avg_fanout(),
+                l.max_fanout
+            )?;
+            if let Some(load) = l.load_factor() {
+                write!(f, ", {} / {} slots ({:.2})", l.keys, l.slots, load)?;
+            }
+            writeln!(f)?;
+        }
+
+        if let Some(c) = &self.chains {
+            writeln!(
+                f,
+                "table: {} / {} slots ({:.2}), {} overflow, chains {:.2} avg / {} max",
+                c.occupied,
+                c.slots,
+                c.load_factor(),
+                c.overflow,
+                c.avg_len(),
+                c.max_len
+            )?;
+        }
+
+        write!(f, "{} bytes", self.bytes)
+    }
+}
+
+/// Tries which can report [`TrieStats`].
+pub trait Stats {
+    fn stats(&self) -> TrieStats;
+}
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+
+    use crate::{hash, simple_hash, sorted, Oneshot};
+
+    use super::Stats;
+
+    #[test]
+    fn shape() {
+        let a = Bump::new();
+        let tuples = [[1, 2, 3], [1, 2, 4], [1, 5, 6], [2, 7, 8]];
+
+        let t: sorted::Trie<i32> = Oneshot::<3>::from_iter(tuples, &a);
+        let s = t.stats();
+        assert_eq!(
+            s.levels.iter().map(|l| l.nodes).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            s.levels.iter().map(|l| l.keys).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+        assert_eq!(s.levels[1].max_fanout, 2);
+        assert_eq!(s.tuples(), 4);
+        assert!(s.bytes > 0);
+
+        // Same shape whatever the layout
+        let h: simple_hash::Trie<i32, 3> = Oneshot::from_iter(tuples, &a);
+        let hs = h.stats();
+        for (l, hl) in s.levels.iter().zip(hs.levels.iter()) {
+            assert_eq!(
+                (l.nodes, l.keys, l.max_fanout),
+                (hl.nodes, hl.keys, hl.max_fanout)
+            );
+            assert!(hl.load_factor().unwrap() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn chains() {
+        let a = Bump::new();
+        let t: hash::Trie<i32, 2> = Oneshot::from_iter((0..100).map(|x| [x % 7, x]), &a);
+        let s = t.stats();
+
+        // Every key of every node lives in the table, or overflows from it
+        let c = s.chains.clone().unwrap();
+        let keys: usize = s.levels.iter().map(|l| l.keys).sum();
+        assert_eq!(c.occupied + c.overflow, keys);
+        assert!(c.max_len >= 1 && c.load_factor() <= 1.0);
+        assert_eq!(s.tuples(), 100);
+        assert!(s.to_string().contains("overflow"));
+    }
+}
@@ -0,0 +1,262 @@
+//! Tries which own their arena.
+//!
+//! Every trie borrows the [`Bump`] it was built in, so it can't outlive the
+//! function which made the arena, nor be kept in a struct without the
+//! arena's lifetime along with it. A [`TrieStore`] owns an [`Arena`] and a
+//! trie built in it, and only lends the trie out for as long as the store
+//! is borrowed:
+//!
+//! ```
+//! use std::collections::HashMap;
+//!
+//! use brie::{sorted, store::TrieStore, Oneshot};
+//!
+//! fn index(edges: &[[u32; 2]]) -> TrieStore<sorted::Trie<'static, u32>> {
+//!     TrieStore::new(|bump| Oneshot::<2>::from_iter(edges.iter().copied(), bump))
+//! }
+//!
+//! let mut indices = HashMap::new();
+//! indices.insert("edge", index(&[[1, 2], [2, 3]]));
+//!
+//! let edge = indices["edge"].trie();
+//! assert_eq!(Oneshot::<2>::intersect(edge, [edge]).count(), 2);
+//! ```
+
+use std::{
+    fmt,
+    mem::{self, ManuallyDrop},
+    ptr::NonNull,
+};
+
+use bumpalo::Bump;
+
+use crate::{
+    arena::{Arena, BuildError},
+    sorted::vec::CollectionAllocError,
+};
+
+/// A trie type which can be kept in a [`TrieStore`].
+///
+/// This is implemented on the `'static` instance of each trie, so that
+/// `sorted::Trie<'static, u32>` names the family of `sorted::Trie<'bump,
+/// u32>`s for every `'bump`.
+pub trait Storable {
+    type Trie<'bump>;
+
+    /// Shortens the arena lifetime of a borrowed trie. This is always just
+    /// `trie`, and only compiles if `Self::Trie` is covariant in `'bump`,
+    /// which is what makes lending out the stored trie sound.
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a>;
+}
+
+/// A trie together with the [`Arena`] it was built in.
+///
+/// The store owns both, so it can be returned from functions, cached and
+/// kept in maps like any other value. [`TrieStore::trie`] lends the trie out
+/// for as long as the store is borrowed.
+pub struct TrieStore<F: Storable> {
+    // The trie really borrows from `*arena`, which lives until `drop`
+    trie: ManuallyDrop<F::Trie<'static>>,
+    // Boxed, so that the arena stays put when the store is moved
+    arena: NonNull<Arena>,
+}
+
+impl<F: Storable> TrieStore<F> {
+    /// Builds a trie with `build` in a new arena without a budget.
+    pub fn new<B>(build: B) -> Self
+    where
+        B: for<'bump> FnOnce(&'bump Bump) -> F::Trie<'bump>,
+    {
+        Self::new_in(Arena::new(), build)
+    }
+
+    /// Builds a trie with `build` in `arena`, which is moved into the store.
+    pub fn new_in<B>(arena: Arena, build: B) -> Self
+    where
+        B: for<'bump> FnOnce(&'bump Bump) -> F::Trie<'bump>,
+    {
+        let guard = ArenaGuard::new(arena);
+        // SAFETY: the arena isn't freed until the trie has been dropped, and
+        // the trie is only lent out for borrows of the store
+        let trie = build(unsafe { &*guard.0.as_ptr() });
+        Self {
+            trie: ManuallyDrop::new(trie),
+            arena: guard.into_inner(),
+        }
+    }
+
+    /// Builds a trie with `build` in `arena`, failing with a [`BuildError`]
+    /// if it doesn't fit in the arena's budget. See
+    /// [`Arena::try_from_iter`].
+    pub fn try_new_in<B>(arena: Arena, build: B) -> Result<Self, BuildError>
+    where
+        B: for<'bump> FnOnce(&'bump Bump) -> Result<F::Trie<'bump>, CollectionAllocError>,
+    {
+        let guard = ArenaGuard::new(arena);
+        // SAFETY: as in `new_in`. On failure nothing borrows the arena any
+        // more, so the guard frees it straight away.
+        let built = unsafe { &*guard.0.as_ptr() }.try_build(build)?;
+        Ok(Self {
+            trie: ManuallyDrop::new(built.trie),
+            arena: guard.into_inner(),
+        })
+    }
+
+    /// The stored trie.
+    #[inline]
+    pub fn trie(&self) -> &F::Trie<'_> {
+        F::shorten(&self.trie)
+    }
+
+    /// Bytes handed out by the arena. See [`Arena::used`].
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.arena().used()
+    }
+
+    /// Bytes the arena has taken from the system allocator. See
+    /// [`Arena::allocated`].
+    #[inline]
+    pub fn allocated(&self) -> usize {
+        self.arena().allocated()
+    }
+
+    // Not public: anyone with a `&Bump` could allocate from it, which would
+    // race with other threads sharing the store.
+    #[inline]
+    fn arena(&self) -> &Arena {
+        // SAFETY: the arena lives as long as the store
+        unsafe { self.arena.as_ref() }
+    }
+}
+
+/// A boxed arena which is freed on drop, so that it isn't leaked if building
+/// the trie fails or panics before the store owns it.
+struct ArenaGuard(NonNull<Arena>);
+
+impl ArenaGuard {
+    fn new(arena: Arena) -> Self {
+        Self(NonNull::from(Box::leak(Box::new(arena))))
+    }
+
+    /// Hands the arena over to the caller, who must free it.
+    fn into_inner(self) -> NonNull<Arena> {
+        let arena = self.0;
+        mem::forget(self);
+        arena
+    }
+}
+
+impl Drop for ArenaGuard {
+    fn drop(&mut self) {
+        // SAFETY: the box was leaked by `new`, and whatever borrowed it is
+        // gone by the time the guard is dropped
+        drop(unsafe { Box::from_raw(self.0.as_ptr()) });
+    }
+}
+
+// The trie and its arena move together, and a `Bump` can be sent to another
+// thread so long as nothing else borrows it.
+unsafe impl<F: Storable> Send for TrieStore<F> where F::Trie<'static>: Send {}
+
+// Shared stores only hand out shared tries, and never the arena itself, so
+// the arena isn't touched until the store is dropped.
+unsafe impl<F: Storable> Sync for TrieStore<F> where F::Trie<'static>: Sync {}
+
+impl<F: Storable> Drop for TrieStore<F> {
+    fn drop(&mut self) {
+        // SAFETY: the trie goes first, while the arena it borrows from is
+        // still there, and neither is touched again
+        unsafe {
+            ManuallyDrop::drop(&mut self.trie);
+            drop(Box::from_raw(self.arena.as_ptr()));
+        }
+    }
+}
+
+impl<F: Storable> fmt::Debug for TrieStore<F>
+where
+    for<'a> F::Trie<'a>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrieStore")
+            .field("trie", self.trie())
+            .field("arena", self.arena())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        arena::{Arena, BuildError},
+        skip_list::SkipList,
+        sorted, Cursor, Oneshot, TryOneshot,
+    };
+
+    use super::TrieStore;
+
+    type Index = TrieStore<sorted::Trie<'static, i32>>;
+
+    fn index(tuples: impl IntoIterator<Item = [i32; 2]>) -> Index {
+        TrieStore::new(|bump| Oneshot::<2>::from_iter(tuples, bump))
+    }
+
+    #[test]
+    fn stored() {
+        let mut indices: HashMap<&str, Index> = HashMap::new();
+        indices.insert("r", index((0..100).map(|x| [x % 10, x])));
+        indices.insert(
+            "s",
+            index((0..100).filter(|x| x % 2 == 0).map(|x| [x % 10, x])),
+        );
+
+        // Moving the stores around leaves their tries valid
+        let indices: Vec<_> = indices.into_iter().collect();
+        let (r, s) = match indices[0].0 {
+            "r" => (indices[0].1.trie(), indices[1].1.trie()),
+            _ => (indices[1].1.trie(), indices[0].1.trie()),
+        };
+        let keys: Vec<_> = Oneshot::<2>::intersect(r, [s]).copied().collect();
+        assert_eq!(keys, [0, 2, 4, 6, 8]);
+
+        let r = Oneshot::<2>::open(r).advance(&4).unwrap();
+        assert_eq!(r.len(), 10);
+    }
+
+    #[test]
+    fn budget() {
+        let small = TrieStore::<SkipList<'static, i32, 2>>::try_new_in(
+            Arena::with_budget(64 * 1024),
+            |bump| TryOneshot::<2>::try_from_iter((0..100).map(|x| [x, x]), bump),
+        )
+        .unwrap();
+        assert_eq!(Oneshot::<2>::open(small.trie()).len(), 100);
+        assert!(small.used() > 0 && small.used() <= small.allocated());
+
+        let big = TrieStore::<SkipList<'static, i32, 2>>::try_new_in(
+            Arena::with_budget(64 * 1024),
+            |bump| TryOneshot::<2>::try_from_iter((0..100_000).map(|x| [x, x]), bump),
+        );
+        assert!(matches!(big, Err(BuildError::OverBudget { .. })));
+    }
+
+    #[test]
+    fn threads() {
+        // Built on one thread, then shared between several others
+        let r = std::thread::spawn(|| index((0..100).map(|x| [x % 10, x])))
+            .join()
+            .unwrap();
+        let r = &r;
+        std::thread::scope(|scope| {
+            for k in 0..4 {
+                scope.spawn(move || {
+                    let c = Oneshot::<2>::open(r.trie()).advance(&k).unwrap();
+                    assert_eq!(c.len(), 10);
+                });
+            }
+        });
+    }
+}
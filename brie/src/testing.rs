@@ -0,0 +1,47 @@
+//! Helpers shared by the backends' tests.
+
+use std::hash::Hasher;
+
+use bumpalo::Bump;
+
+use crate::{Cursor, Oneshot};
+
+/// Hashes everything to zero, so that every key collides.
+#[derive(Default)]
+pub(crate) struct Colliding;
+
+impl Hasher for Colliding {
+    fn write(&mut self, _: &[u8]) {}
+
+    fn finish(&self) -> u64 {
+        0
+    }
+}
+
+/// Builds two tries of `T` on one thread, then intersects and walks them
+/// from several others at once.
+pub(crate) fn shared<'b, T>(bump: &'b Bump)
+where
+    T: Oneshot<'b, 2, Value = i32> + Sync + 'b,
+{
+    let r: &T = bump.alloc(T::from_iter((0..1000).map(|x| [x % 100, x]), bump));
+    let s: &T = bump.alloc(T::from_iter(
+        (0..1000).step_by(3).map(|x| [x % 100, x]),
+        bump,
+    ));
+    let expected = r.intersect([s]).count();
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                scope.spawn(move || {
+                    assert_eq!(r.intersect([s]).count(), expected);
+                    r.open().advance(&(i * 25)).unwrap().len()
+                })
+            })
+            .collect();
+        for w in workers {
+            assert_eq!(w.join().unwrap(), 10);
+        }
+    });
+}
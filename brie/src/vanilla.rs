@@ -6,85 +6,234 @@
 //! vanilla-flavored :^)
 
 use std::{
-    hash::{Hash, Hasher},
+    hash::{BuildHasher, BuildHasherDefault, Hash},
     marker::PhantomData,
-    ptr::NonNull,
-    task::RawWakerVTable,
+    mem,
 };
 
 use bumpalo::{boxed::Box, Bump};
 use hashbrown::{hash_map::DefaultHashBuilder, raw::RawTable, BumpWrapper, HashMap};
 
-use crate::Oneshot;
+use crate::{
+    leaf::{Semantics, Set},
+    stats::{Stats, TrieStats},
+    store::Storable,
+    Cursor, HashOneshot, IntoIterType, NodeCursor, Oneshot,
+};
 
 /// A vanilla hash trie!
 /// Nothing special, just a bunch of nested HashMaps.
 #[derive(Debug, Clone)]
-pub struct Trie<T>(pub HashMap<T, Self>);
+pub struct Trie<T, B = DefaultHashBuilder>(pub HashMap<T, Self, B>);
 
-impl<T> Default for Trie<T> {
+impl<T, B: Default> Default for Trie<T, B> {
     fn default() -> Self {
         Self(HashMap::default())
     }
 }
 
-impl<'bump, T, const N: usize> Oneshot<'bump, N> for Trie<T>
+impl<'bump, T, B, const N: usize> Oneshot<'bump, N> for Trie<T, B>
 where
     T: Eq + Hash + Clone,
     T: 'bump,
+    B: BuildHasher + Default + Clone + 'bump,
 {
     type Value = T;
     type IVal = &'bump T;
-    type KeyIter<const M: usize> = impl Iterator<Item = &'bump T>;
+    type KeyIter<const M: usize> = iter_type!('bump, &'bump T);
+    type Cursor<'a>
+        = NodeCursor<'a, Self>
+    where
+        Self: 'a;
 
     fn from_iter<I: IntoIterator<Item = [T; N]>>(iter: I, bump: &'bump Bump) -> Self {
-        let mut res = Self::default();
-
-        for tuple in iter.into_iter() {
-            let mut trie = &mut res;
-            for v in tuple {
-                trie = trie.0.entry(v.clone()).or_default()
-            }
-        }
+        Self::from_iter_with_hasher(iter, B::default(), bump)
+    }
 
-        res
+    fn open(&self) -> NodeCursor<'_, Self> {
+        NodeCursor::new(self)
     }
 
-    fn intersect<'a, const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
         self.0
             .keys()
             .filter(move |k| others.iter().all(|idx| idx.0.contains_key(k)))
+            .into_iter_type()
     }
+}
 
-    fn advance(&'bump self, v: &Self::Value) -> Option<&'bump Self> {
-        self.0.get(v)
+impl<'a, T, B> Cursor<'a> for NodeCursor<'a, Trie<T, B>>
+where
+    T: Eq + Hash + 'a,
+    B: BuildHasher + 'a,
+{
+    type Value = T;
+    type Keys = iter_type!('a, &'a T);
+    type Intersect<const M: usize> = iter_type!('a, (&'a T, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.node.0.len()
+    }
+
+    fn keys(&self) -> Self::Keys {
+        self.node.0.keys().into_iter_type()
+    }
+
+    fn advance(&self, v: &T) -> Option<Self> {
+        self.node.0.get(v).map(|t| self.child(t))
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        let this = *self;
+        this.node
+            .0
+            .iter()
+            .filter_map(move |(k, t)| {
+                let children = crate::try_from_fn(|i| {
+                    let c = &others[i];
+                    Some(c.child(c.node.0.get(k)?))
+                })?;
+                Some((k, this.child(t), children))
+            })
+            .into_iter_type()
     }
 }
 
-impl<'b, T> BumpTrie<'b, T>
+impl<'bump, T, B, const N: usize> HashOneshot<'bump, N> for Trie<T, B>
 where
     T: Eq + Hash + Clone,
+    T: 'bump,
+    B: BuildHasher + Default + Clone + 'bump,
 {
-    fn new_in(bump: &'b Bump) -> Self {
-        Self(HashMap::new_in(BumpWrapper(bump)))
+    type BuildHasher = B;
+
+    fn from_iter_with_hasher<I: IntoIterator<Item = [T; N]>>(
+        iter: I,
+        hasher: B,
+        _bump: &'bump Bump,
+    ) -> Self {
+        let mut res = Self(HashMap::with_hasher(hasher.clone()));
+
+        for tuple in iter.into_iter() {
+            let mut trie = &mut res;
+            for v in tuple {
+                trie = trie
+                    .0
+                    .entry(v.clone())
+                    .or_insert_with(|| Self(HashMap::with_hasher(hasher.clone())))
+            }
+        }
+
+        res
+    }
+}
+
+impl<'b, T, B> BumpTrie<'b, T, B>
+where
+    T: Eq + Hash + Clone,
+{
+    fn new_in(hasher: B, bump: &'b Bump) -> Self {
+        Self(HashMap::with_hasher_in(hasher, BumpWrapper(bump)))
     }
 }
 
 /// A hash trie allocated on a bump allocator.
 #[derive(Debug, Clone)]
-pub struct BumpTrie<'a, T>(pub HashMap<T, Self, DefaultHashBuilder, BumpWrapper<'a>>);
+pub struct BumpTrie<'a, T, B = DefaultHashBuilder>(pub HashMap<T, Self, B, BumpWrapper<'a>>);
 
-impl<'bump, T, const N: usize> Oneshot<'bump, N> for BumpTrie<'bump, T>
+impl<'bump, T, B, const N: usize> Oneshot<'bump, N> for BumpTrie<'bump, T, B>
 where
     T: Eq + Hash + Clone,
     T: 'bump,
+    B: BuildHasher + Default + Clone + 'bump,
 {
     type Value = T;
     type IVal = &'bump T;
-    type KeyIter<const M: usize> = impl Iterator<Item = &'bump T>;
+    type KeyIter<const M: usize> = iter_type!('bump, &'bump T);
+    type Cursor<'a>
+        = NodeCursor<'a, Self>
+    where
+        Self: 'a;
 
     fn from_iter<I: IntoIterator<Item = [T; N]>>(iter: I, bump: &'bump Bump) -> Self {
-        let mut res = Self::new_in(bump);
+        Self::from_iter_with_hasher(iter, B::default(), bump)
+    }
+
+    fn open(&self) -> NodeCursor<'_, Self> {
+        NodeCursor::new(self)
+    }
+
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
+        self.0
+            .keys()
+            .filter(move |k| others.iter().all(|idx| idx.0.contains_key(k)))
+            .into_iter_type()
+    }
+}
+
+impl<'a, 'bump: 'a, T, B> Cursor<'a> for NodeCursor<'a, BumpTrie<'bump, T, B>>
+where
+    T: Eq + Hash + 'a,
+    B: BuildHasher + 'a,
+{
+    type Value = T;
+    type Keys = iter_type!('a, &'a T);
+    type Intersect<const M: usize> = iter_type!('a, (&'a T, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.node.0.len()
+    }
+
+    fn keys(&self) -> Self::Keys {
+        self.node.0.keys().into_iter_type()
+    }
+
+    fn advance(&self, v: &T) -> Option<Self> {
+        self.node.0.get(v).map(|t| self.child(t))
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        let this = *self;
+        this.node
+            .0
+            .iter()
+            .filter_map(move |(k, t)| {
+                let children = crate::try_from_fn(|i| {
+                    let c = &others[i];
+                    Some(c.child(c.node.0.get(k)?))
+                })?;
+                Some((k, this.child(t), children))
+            })
+            .into_iter_type()
+    }
+}
+
+impl<'bump, T, B, const N: usize> HashOneshot<'bump, N> for BumpTrie<'bump, T, B>
+where
+    T: Eq + Hash + Clone,
+    T: 'bump,
+    B: BuildHasher + Default + Clone + 'bump,
+{
+    type BuildHasher = B;
+
+    fn from_iter_with_hasher<I: IntoIterator<Item = [T; N]>>(
+        iter: I,
+        hasher: B,
+        bump: &'bump Bump,
+    ) -> Self {
+        let mut res = Self::new_in(hasher.clone(), bump);
 
         for tuple in iter.into_iter() {
             let mut trie = &mut res;
@@ -92,103 +241,302 @@ where
                 trie = trie
                     .0
                     .entry(v.clone())
-                    .or_insert_with(|| Self::new_in(bump))
+                    .or_insert_with(|| Self::new_in(hasher.clone(), bump))
             }
         }
 
         res
     }
+}
 
-    fn intersect<'a, const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
-        self.0
-            .keys()
-            .filter(move |k| others.iter().all(|idx| idx.0.contains_key(k)))
-    }
+/// A hash trie over raw hashbrown tables, keyed by hash. Keys are also told
+/// apart by value, so that keys whose hashes collide are still kept apart.
+///
+/// `S` decides whether duplicate tuples collapse into one leaf ([`Set`]) or
+/// are counted on it ([`crate::leaf::Bag`]). `B` hashes keys; tries can only
+/// be intersected if they were built with the same hasher.
+///
+/// A trie is [`Sync`] whenever its keys and hasher are, so one trie can be
+/// read from several threads at once. It isn't [`Send`], since its tables
+/// hold on to the arena they grow in.
+pub struct FancyTrie<'a, T, S: Semantics = Set, B = BuildHasherDefault<wyhash::WyHash>> {
+    root: Table<'a, T, S>,
+    hasher: B,
+}
 
-    fn advance(&'bump self, v: &Self::Value) -> Option<&'bump Self> {
-        self.0.get(v)
+impl<'a, T, S: Semantics, B> FancyTrie<'a, T, S, B> {
+    /// The hasher this trie was built with; build other tries with a clone
+    /// of it to intersect against this one.
+    #[inline]
+    pub fn hasher(&self) -> &B {
+        &self.hasher
     }
 }
 
-pub struct FancyTrie<'a, T>(RawTable<Entry<'a, T>, BumpWrapper<'a>>);
+/// One node of a [`FancyTrie`].
+pub struct Table<'a, T, S: Semantics = Set>(RawTable<Entry<'a, T, S>, BumpWrapper<'a>>);
+
+// A table keeps a `&Bump` to grow with, which stops it being `Sync`. But it
+// only ever allocates while inserting, through `&mut self`, and isn't `Clone`,
+// so shared tables are read-only and can be read from any number of threads.
+// It isn't `Send`: a table sent away could grow on another thread while the
+// arena's owner allocates from it too.
+unsafe impl<'a, T: Sync, S: Semantics> Sync for Table<'a, T, S> where S::Count: Sync {}
 
-pub struct Entry<'a, T> {
+impl<'a, T, S: Semantics> Table<'a, T, S> {
+    fn new_in(bump: &'a Bump) -> Self {
+        Self(RawTable::new_in(BumpWrapper(bump)))
+    }
+}
+
+pub struct Entry<'a, T, S: Semantics = Set> {
     hash: u64,
-    ptr: Ptr<'a, T>,
+    ptr: Ptr<'a, T, S>,
 }
 
-pub enum Ptr<'a, T> {
-    Data(NonNull<Data<'a, T>>),
-    Trie(FancyTrie<'a, T>),
+pub enum Ptr<'a, T, S: Semantics = Set> {
+    /// Leaves are boxed in the arena, so they're dropped along with the trie.
+    Data(Box<'a, Data<'a, T, S>>),
+    Trie(Table<'a, T, S>),
 }
 
-impl<'a, T> Ptr<'a, T> {
-    fn get_trie(&mut self) -> Option<&mut FancyTrie<'a, T>> {
+impl<'a, T, S: Semantics> Ptr<'a, T, S> {
+    fn get_trie(&mut self) -> Option<&mut Table<'a, T, S>> {
         match self {
             Ptr::Data(_) => None,
             Ptr::Trie(ft) => Some(ft),
         }
     }
 
-    fn get_data(&mut self) -> Option<&mut Data<'a, T>> {
+    fn get_data(&mut self) -> Option<&mut Data<'a, T, S>> {
         match self {
-            Ptr::Data(d) => Some(unsafe { d.as_mut() }),
+            Ptr::Data(d) => Some(&mut **d),
             Ptr::Trie(_) => None,
         }
     }
+
+    /// Any one of the leaves under this pointer. Every leaf under a key
+    /// agrees on that key's value, so this is how we get keys back out.
+    fn first_leaf(&self) -> &Data<'a, T, S> {
+        let mut ptr = self;
+        loop {
+            match ptr {
+                Ptr::Data(d) => return d,
+                // Tables are only ever created to be inserted into, so
+                // they're never empty
+                Ptr::Trie(t) => ptr = unsafe { &t.0.iter().next().unwrap().as_ref().ptr },
+            }
+        }
+    }
 }
 
-pub struct Data<'a, T> {
+/// A leaf: one distinct tuple, plus however many times it was inserted.
+pub struct Data<'a, T, S: Semantics = Set> {
     data: T,
-    next: Option<NonNull<Data<'a, T>>>,
+    count: S::Count,
     _p: PhantomData<&'a T>,
-    // next: Option<&'a usize>,
 }
 
-impl<'bump, T, const N: usize> Oneshot<'bump, N> for FancyTrie<'bump, [T; N]>
+impl<'a, T, S: Semantics> Data<'a, T, S> {
+    #[inline]
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// The multiplicity of this tuple; always 1 under set semantics.
+    #[inline]
+    pub fn count(&self) -> usize {
+        S::get(&self.count)
+    }
+}
+
+impl<'bump, T, const N: usize, S: Semantics, B> Oneshot<'bump, N> for FancyTrie<'bump, [T; N], S, B>
 where
     T: Eq + Hash + Clone,
     T: 'bump,
+    B: BuildHasher + Default + 'bump,
 {
     type Value = T;
-    type IVal = &'bump Entry<'bump, [T; N]>;
-    type KeyIter<const M: usize> = impl Iterator<Item = Self::IVal>;
+    type IVal = &'bump Entry<'bump, [T; N], S>;
+    type KeyIter<const M: usize> = iter_type!('bump, Self::IVal);
+    type Cursor<'a>
+        = FancyCursor<'a, 'bump, [T; N], S, B>
+    where
+        Self: 'a;
 
     fn from_iter<I: IntoIterator<Item = [T; N]>>(iter: I, bump: &'bump Bump) -> Self {
-        let mut res = Self(RawTable::new_in(BumpWrapper(bump)));
+        Self::from_iter_with_hasher(iter, B::default(), bump)
+    }
+
+    fn open(&self) -> Self::Cursor<'_> {
+        FancyCursor {
+            trie: self,
+            table: Some(&self.root),
+            depth: 0,
+        }
+    }
+
+    fn intersect<const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
+        unsafe {
+            self.root
+                .0
+                .iter()
+                .filter(move |v| {
+                    let v = v.as_ref();
+                    let key = &v.ptr.first_leaf().data[0];
+                    let same = |o: &Entry<'bump, [T; N], S>| {
+                        o.hash == v.hash && &o.ptr.first_leaf().data[0] == key
+                    };
+                    others
+                        .iter()
+                        .all(|idx| idx.root.0.get(v.hash, same).is_some())
+                })
+                .map(|x| x.as_ref())
+                .into_iter_type()
+        }
+    }
+}
+
+/// A [`Cursor`] into a [`FancyTrie`]: the table at this node, or `None` once
+/// we've reached a leaf.
+pub struct FancyCursor<'a, 'bump, T, S: Semantics, B> {
+    trie: &'a FancyTrie<'bump, T, S, B>,
+    table: Option<&'a Table<'bump, T, S>>,
+    depth: usize,
+}
+
+impl<'a, 'bump, T, S: Semantics, B> FancyCursor<'a, 'bump, T, S, B> {
+    fn entries(&self) -> impl Iterator<Item = &'a Entry<'bump, T, S>> + 'a {
+        self.table
+            .into_iter()
+            .flat_map(|t| unsafe { t.0.iter() }.map(|b| unsafe { b.as_ref() }))
+    }
+
+    /// The cursor under `e`, one of this node's entries.
+    fn child(&self, e: &'a Entry<'bump, T, S>) -> Self {
+        Self {
+            trie: self.trie,
+            table: match &e.ptr {
+                Ptr::Trie(t) => Some(t),
+                Ptr::Data(_) => None,
+            },
+            depth: self.depth + 1,
+        }
+    }
+}
+
+impl<'a, 'bump, T: Eq, const N: usize, S: Semantics, B> FancyCursor<'a, 'bump, [T; N], S, B> {
+    /// The entry for `key`, which hashes to `hash`. Keys which collide share
+    /// a hash, so they're told apart by value.
+    fn find(&self, hash: u64, key: &T) -> Option<&'a Entry<'bump, [T; N], S>> {
+        let depth = self.depth;
+        self.table?.0.get(hash, |e| {
+            e.hash == hash && &e.ptr.first_leaf().data[depth] == key
+        })
+    }
+}
+
+impl<'a, 'bump, T, S: Semantics, B> Clone for FancyCursor<'a, 'bump, T, S, B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, 'bump, T, S: Semantics, B> Copy for FancyCursor<'a, 'bump, T, S, B> {}
+
+impl<'a, 'bump: 'a, T, const N: usize, S: Semantics, B> Cursor<'a>
+    for FancyCursor<'a, 'bump, [T; N], S, B>
+where
+    T: Eq + Hash + 'bump,
+    B: BuildHasher,
+{
+    type Value = T;
+    type Keys = iter_type!('a, &'a T);
+    type Intersect<const M: usize> = iter_type!('a, (&'a T, Self, [Self; M]));
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.table.map_or(0, |t| t.0.len())
+    }
+
+    fn keys(&self) -> Self::Keys {
+        let depth = self.depth;
+        self.entries()
+            .map(move |e| &e.ptr.first_leaf().data[depth])
+            .into_iter_type()
+    }
+
+    fn advance(&self, v: &T) -> Option<Self> {
+        let hash = self.trie.hasher.hash_one(v);
+        self.find(hash, v).map(|e| self.child(e))
+    }
+
+    fn intersect_with<const M: usize>(&self, others: [Self; M]) -> Self::Intersect<M> {
+        let this = *self;
+        self.entries()
+            .filter_map(move |e| {
+                let key = &e.ptr.first_leaf().data[this.depth];
+                let children = crate::try_from_fn(|i| {
+                    let c = &others[i];
+                    Some(c.child(c.find(e.hash, key)?))
+                })?;
+
+                Some((key, this.child(e), children))
+            })
+            .into_iter_type()
+    }
+}
+
+impl<'bump, T, const N: usize, S: Semantics, B> HashOneshot<'bump, N>
+    for FancyTrie<'bump, [T; N], S, B>
+where
+    T: Eq + Hash + Clone,
+    T: 'bump,
+    B: BuildHasher + Default + 'bump,
+{
+    type BuildHasher = B;
+
+    fn from_iter_with_hasher<I: IntoIterator<Item = [T; N]>>(
+        iter: I,
+        hasher: B,
+        bump: &'bump Bump,
+    ) -> Self {
+        let mut res = Self {
+            root: Table::new_in(bump),
+            hasher,
+        };
 
         for tuple in iter.into_iter() {
-            let mut trie = &mut res;
+            let mut trie = &mut res.root;
             for (i, v) in tuple.iter().enumerate() {
-                let hash = {
-                    let mut hasher = wyhash::WyHash::default();
-                    v.hash(&mut hasher);
-                    hasher.finish()
-                };
+                let hash = res.hasher.hash_one(v);
                 if i == N - 1 {
-                    let d = Box::new_in(
-                        Data {
-                            data: tuple.clone(),
-                            next: None,
-                            _p: PhantomData,
-                        },
-                        bump,
-                    );
-
-                    if let Some(v) = trie.0.get_mut(hash, |x| x.hash == hash) {
-                        let mut t = v.ptr.get_data().unwrap();
-
-                        while t.next.is_some() {
-                            // We can't use while let here bc of borrowck limits
-                            // with linked lists
-                            // fuckin rust smfh
-                            t = unsafe { t.next.unwrap().as_mut() };
-                        }
-                        t.next = Some(NonNull::from(Box::leak(d)));
+                    // Leaves are told apart by value too
+                    let same = |x: &Entry<'bump, [T; N], S>| match &x.ptr {
+                        Ptr::Data(d) => x.hash == hash && d.data == tuple,
+                        Ptr::Trie(_) => unreachable!(),
+                    };
+                    if let Some(v) = trie.0.get_mut(hash, same) {
+                        // It's the tuple we already have, so just record the
+                        // duplicate on its leaf.
+                        S::bump(&mut v.ptr.get_data().unwrap().count);
                     } else {
+                        let d = Box::new_in(
+                            Data {
+                                data: tuple.clone(),
+                                count: S::one(),
+                                _p: PhantomData,
+                            },
+                            bump,
+                        );
                         let value = Entry {
                             hash,
-                            ptr: Ptr::Data(NonNull::from(Box::leak(d))),
+                            ptr: Ptr::Data(d),
                         };
                         trie.0
                             .insert_entry(hash, value, |v| v.hash)
@@ -197,12 +545,17 @@ where
                             .unwrap();
                     };
                 } else {
-                    trie = if let Some(b) = trie.0.find(hash, |x| x.hash == hash) {
+                    // Keys which collide are told apart by value too. A new
+                    // key's table is filled before anything else looks it up.
+                    let same = |x: &Entry<'bump, [T; N], S>| {
+                        x.hash == hash && x.ptr.first_leaf().data[i] == *v
+                    };
+                    trie = if let Some(b) = trie.0.find(hash, same) {
                         unsafe { b.as_mut().ptr.get_trie().unwrap() }
                     } else {
                         let value = Entry {
                             hash,
-                            ptr: Ptr::Trie(Self(RawTable::new_in(BumpWrapper(bump)))),
+                            ptr: Ptr::Trie(Table::new_in(bump)),
                         };
                         trie.0
                             .insert_entry(hash, value, |v| v.hash)
@@ -216,27 +569,150 @@ where
 
         res
     }
+}
 
-    fn intersect<'a, const M: usize>(&'bump self, others: [&'bump Self; M]) -> Self::KeyIter<M> {
-        unsafe {
-            self.0
-                .iter()
-                .filter(move |v| {
-                    let v = v.as_ptr();
-                    let hash = {
-                        let mut hasher = wyhash::WyHash::default();
-                        v.hash(&mut hasher);
-                        hasher.finish()
-                    };
-                    others
-                        .iter()
-                        .all(|idx| idx.0.get(hash, |o| o.hash == hash).is_some())
-                })
-                .map(|x| x.as_ref())
-        }
+impl<T: Eq + Hash, B: BuildHasher> Stats for Trie<T, B> {
+    fn stats(&self) -> TrieStats {
+        TrieStats::walk(NodeCursor::new(self), |c| {
+            let cap = c.node.0.capacity();
+            (cap, cap * mem::size_of::<(T, Self)>())
+        })
+    }
+}
+
+impl<T, B> Storable for BumpTrie<'static, T, B> {
+    type Trie<'bump> = BumpTrie<'bump, T, B>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'bump, T: Eq + Hash, B: BuildHasher> Stats for BumpTrie<'bump, T, B> {
+    fn stats(&self) -> TrieStats {
+        TrieStats::walk(NodeCursor::new(self), |c| {
+            let cap = c.node.0.capacity();
+            (cap, cap * mem::size_of::<(T, Self)>())
+        })
+    }
+}
+
+impl<T, S: Semantics, B> Storable for FancyTrie<'static, T, S, B> {
+    type Trie<'bump> = FancyTrie<'bump, T, S, B>;
+
+    fn shorten<'a, 'b: 'a>(trie: &'a Self::Trie<'b>) -> &'a Self::Trie<'a> {
+        trie
+    }
+}
+
+impl<'bump, T, const N: usize, S: Semantics, B> Stats for FancyTrie<'bump, [T; N], S, B>
+where
+    T: Eq + Hash + Clone + 'bump,
+    B: BuildHasher + Default + 'bump,
+{
+    fn stats(&self) -> TrieStats {
+        TrieStats::walk(self.open(), |c| {
+            let buckets = c.table.map_or(0, |t| t.0.buckets());
+            // Leaves are allocated one by one, under the last level's entries
+            let leaves = c
+                .entries()
+                .filter(|e| matches!(e.ptr, Ptr::Data(_)))
+                .count();
+            let bytes = buckets * mem::size_of::<Entry<'bump, [T; N], S>>()
+                + leaves * mem::size_of::<Data<'bump, [T; N], S>>();
+            (buckets, bytes)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{hash::BuildHasherDefault, rc::Rc};
+
+    use bumpalo::Bump;
+
+    use crate::{
+        leaf::Bag,
+        testing::{self, Colliding},
+        Cursor, Oneshot,
+    };
+
+    use super::FancyTrie;
+
+    #[test]
+    fn drops() {
+        let a = Bump::new();
+        let vals = (0..10).map(Rc::new).collect::<Vec<_>>();
+        let t: FancyTrie<[Rc<i32>; 2]> = Oneshot::from_iter(
+            (0..20).map(|i| [vals[i % 10].clone(), vals[i / 2].clone()]),
+            &a,
+        );
+        assert!(vals.iter().all(|v| Rc::strong_count(v) > 1));
+
+        // Leaves are dropped along with their tables
+        drop(t);
+        assert!(vals.iter().all(|v| Rc::strong_count(v) == 1));
+    }
+
+    #[test]
+    fn collisions() {
+        let a = Bump::new();
+        let tuples = [[1, 2], [3, 4], [1, 2], [5, 6], [3, 4], [1, 2]];
+        let t: FancyTrie<[i32; 2], Bag, BuildHasherDefault<Colliding>> =
+            Oneshot::from_iter(tuples, &a);
+
+        // Every key collides, but each one still only leads to its own
+        // tuples
+        let root = t.open();
+        let mut keys: Vec<_> = root.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, vec![1, 3, 5]);
+
+        let c = root.advance(&1).unwrap();
+        assert_eq!(c.keys().copied().collect::<Vec<_>>(), vec![2]);
+        assert!(c.advance(&4).is_none());
+        let leaf = c.entries().next().unwrap().ptr.first_leaf();
+        assert_eq!((*leaf.data(), leaf.count()), ([1, 2], 3));
+        assert_eq!(
+            c.intersect_with([c])
+                .map(|(k, _, _)| *k)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        let c = root.advance(&3).unwrap();
+        assert_eq!(c.keys().copied().collect::<Vec<_>>(), vec![4]);
+        assert!(root.advance(&2).is_none());
+        let other: FancyTrie<[i32; 2], Bag, BuildHasherDefault<Colliding>> =
+            Oneshot::from_iter([[3, 9], [7, 8]], &a);
+        assert_eq!(t.intersect([&other]).count(), 1);
+    }
+
+    #[test]
+    fn fancy_cursor() {
+        let a = Bump::new();
+        let t: FancyTrie<[i32; 2]> = Oneshot::from_iter([[1, 2], [1, 3], [4, 5]], &a);
+        let t2: FancyTrie<[i32; 2]> = Oneshot::from_iter([[1, 3], [1, 7]], &a);
+
+        let root = t.open();
+        let mut keys: Vec<_> = root.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, vec![1, 4]);
+
+        let c = root.advance(&1).unwrap();
+        let c2 = t2.open().advance(&1).unwrap();
+        assert_eq!(
+            c.intersect_with([c2])
+                .map(|(k, _, _)| *k)
+                .collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert_eq!(c.advance(&2).unwrap().keys().count(), 0);
+        assert!(root.advance(&2).is_none());
     }
 
-    fn advance(&'bump self, v: &Self::Value) -> Option<&'bump Self> {
-        todo!()
+    #[test]
+    fn shared() {
+        testing::shared::<FancyTrie<[i32; 2]>>(&Bump::new());
     }
 }